@@ -1,17 +1,23 @@
-use soksak_lib::{config, output, transcribe, translate};
+use soksak_lib::{
+    checkpoint, config, ffmpeg_decoder, input, output, subtitle, transcribe, translate,
+};
 
 use anyhow::Context;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use config::Language;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use config::{TranscriptionEngine, WhisperConfig};
+use config::{
+    AudioChannel, AudioStream, BilingualOrder, HallucinationAction, OutputConfig, SubtitleFormat,
+    TranscriptionEngine, WhisperConfig,
+};
+use transcribe::checkpoint::{InputFingerprint, TranscriptSource};
 use transcribe::whisper_cpp::Whisper;
 #[cfg(feature = "apple")]
 use transcribe::whisperkit::WhisperKit;
+use translate::memory::TranslationMemory;
 
 #[derive(Parser)]
 #[command(name = "soksak")]
@@ -35,11 +41,38 @@ enum Commands {
         /// Input language (default: auto)
         #[arg(short, long, default_value = "auto")]
         lang: Language,
+
+        /// Start transcribing at this time, e.g. `40:00` or `2400` (default: beginning)
+        #[arg(long, value_parser = parse_time)]
+        start: Option<i64>,
+
+        /// Stop transcribing at this time, e.g. `1:35:00` (default: end of input)
+        #[arg(long, value_parser = parse_time)]
+        end: Option<i64>,
+
+        /// Audio stream to transcribe: index among audio streams (`0` is the first) or language tag, e.g. `jpn`
+        #[arg(long)]
+        audio_stream: Option<AudioStream>,
+
+        /// Audio channel to transcribe: `left`, `right` or `mix` (default: from profile, or mix)
+        #[arg(long)]
+        audio_channel: Option<AudioChannel>,
+
+        /// Continue an interrupted run from its transcription and translation checkpoints
+        #[arg(long)]
+        resume: bool,
+
+        /// Neither reuse nor record translations in the translation memory
+        #[arg(long)]
+        no_cache: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
     },
 
     // Run translation
     Translate {
-        /// Input video file: MUST BE json from 'Run' command
+        /// Input transcript: `.transcript.json` from the 'Run' command, or an SRT/WebVTT file
         input: PathBuf,
 
         /// Configuration profile or file path
@@ -49,13 +82,149 @@ enum Commands {
         /// Input language (default: auto)
         #[arg(short, long, default_value = "auto")]
         lang: Language,
+
+        /// Continue an interrupted translation from its checkpoint
+        #[arg(long)]
+        resume: bool,
+
+        /// Neither reuse nor record translations in the translation memory
+        #[arg(long)]
+        no_cache: bool,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Manage the translation memory in ~/.soksak/translation_memory.jsonl
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Delete every remembered translation
+    Clear,
+
+    /// Write the remembered translations to a JSON file
+    Export {
+        /// Output JSON file
+        output: PathBuf,
     },
 }
 
+#[derive(Args)]
+struct OutputArgs {
+    /// Subtitle formats to write, e.g. `srt,vtt,ass` (default: from profile, or srt)
+    #[arg(short, long, value_delimiter = ',')]
+    format: Vec<SubtitleFormat>,
+
+    /// Show both translated and original text in SRT/VTT cues
+    #[arg(long)]
+    bilingual: bool,
+
+    /// Order of lines in bilingual cues: `translated-first` or `original-first` (implies --bilingual)
+    #[arg(long)]
+    bilingual_order: Option<BilingualOrder>,
+}
+
+impl OutputArgs {
+    /// Overrides the profile's output settings with command-line arguments.
+    fn apply(&self, conf: &mut OutputConfig) {
+        if !self.format.is_empty() {
+            conf.formats = Some(self.format.clone());
+        }
+        if self.bilingual || self.bilingual_order.is_some() {
+            let bilingual = conf.bilingual.get_or_insert_with(Default::default);
+            if let Some(order) = self.bilingual_order {
+                bilingual.order = Some(order);
+            }
+        }
+    }
+}
+
+/// Writes or reuses the translation brief shared by all target languages.
+async fn prepare_brief(
+    translate_config: &config::Translate,
+    target_langs: &[Language],
+    app_config: &config::AppConfig,
+    segments: &[transcribe::TranscriptSegment],
+    path: &Path,
+) -> anyhow::Result<Option<translate::brief::TranslationBrief>> {
+    let pb = ProgressBar::new_spinner();
+    pb.enable_steady_tick(Duration::from_millis(100));
+    let brief = translate::brief::prepare(
+        translate_config,
+        target_langs,
+        app_config,
+        segments,
+        Some(path),
+        &pb,
+    )
+    .await;
+    pb.finish_and_clear();
+    brief
+}
+
+/// Summarizes the transcript once for all target languages when windows are
+/// translated in parallel.
+async fn prepare_summary(
+    translate_config: &config::Translate,
+    app_config: &config::AppConfig,
+    segments: &[transcribe::TranscriptSegment],
+) -> anyhow::Result<Option<String>> {
+    let pb = ProgressBar::new_spinner();
+    pb.enable_steady_tick(Duration::from_millis(100));
+    let summary = translate::prepare_summary(translate_config, app_config, segments).await;
+    pb.finish_and_clear();
+    summary
+}
+
+/// The translation memory to use, unless `--no-cache` was given.
+fn memory_path(no_cache: bool) -> anyhow::Result<Option<PathBuf>> {
+    if no_cache {
+        return Ok(None);
+    }
+    TranslationMemory::default_path().map(Some)
+}
+
+fn parse_time(s: &str) -> Result<i64, String> {
+    ffmpeg_decoder::parse_time(s).map_err(|e| e.to_string())
+}
+
+fn report_hallucinations(
+    flagged: &[transcribe::hallucination::FlaggedSegment],
+    action: HallucinationAction,
+) {
+    if flagged.is_empty() {
+        return;
+    }
+
+    let verb = match action {
+        HallucinationAction::Drop => "Dropped",
+        HallucinationAction::Flag => "Flagged",
+    };
+    println!(
+        "{} {} segment(s) that look hallucinated:",
+        verb,
+        flagged.len()
+    );
+    for segment in flagged {
+        println!(
+            "  [{} --> {}] {:?}: {}",
+            output::format_timestamp(segment.start),
+            output::format_timestamp(segment.end),
+            segment.text.trim(),
+            segment.reason
+        );
+    }
+}
+
 fn resolve_profile_path(profile: &str) -> anyhow::Result<PathBuf> {
-    if profile.starts_with("~/") {
+    if let Some(rest) = profile.strip_prefix("~/") {
         let home = dirs::home_dir().context("Could not find home directory")?;
-        return Ok(home.join(&profile[2..]));
+        return Ok(home.join(rest));
     }
 
     let path = PathBuf::from(profile);
@@ -69,6 +238,65 @@ fn resolve_profile_path(profile: &str) -> anyhow::Result<PathBuf> {
         .join(format!("{}.yaml", profile)))
 }
 
+fn save_subtitles(
+    parent_dir: &Path,
+    file_stem: &str,
+    source: &Path,
+    target_lang: &Language,
+    segments: &[translate::TranslatedSegment],
+    output_config: &OutputConfig,
+) -> anyhow::Result<()> {
+    let bilingual = output_config.bilingual.as_ref();
+
+    let laid_out;
+    let segments = match &output_config.layout {
+        Some(layout) => {
+            let rules = subtitle::LayoutRules::for_language(layout, target_lang);
+            laid_out = subtitle::layout_translation(segments.to_vec(), &rules);
+            &laid_out[..]
+        }
+        None => segments,
+    };
+
+    for format in output_config.formats() {
+        let path = parent_dir.join(format!("{}.{}", file_stem, format.extension()));
+        match format {
+            SubtitleFormat::Srt => {
+                output::save_srt(&path, segments, bilingual)?;
+                println!("Saved SRT to {:?}", path);
+            }
+            SubtitleFormat::Vtt => {
+                let source_name = source
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let notes = vec![
+                    format!("Source: {}", source_name),
+                    format!("Language: {}", target_lang),
+                ];
+                output::save_vtt(
+                    &path,
+                    segments,
+                    &output_config.vtt.clone().unwrap_or_default(),
+                    bilingual,
+                    &notes,
+                )?;
+                println!("Saved WebVTT to {:?}", path);
+            }
+            SubtitleFormat::Ass => {
+                output::save_ass(
+                    &path,
+                    segments,
+                    &output_config.ass.clone().unwrap_or_default(),
+                )?;
+                println!("Saved ASS to {:?}", path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
@@ -78,7 +306,15 @@ async fn main() -> anyhow::Result<()> {
             input,
             profile,
             lang,
+            start,
+            end,
+            audio_stream,
+            audio_channel,
+            resume,
+            no_cache,
+            output: output_args,
         } => {
+            let range = ffmpeg_decoder::TimeRange::new(start, end)?;
             let app_config = config::load_app_config().context("Failed to load app config")?;
 
             let run_config = if let Some(p) = profile {
@@ -92,98 +328,205 @@ async fn main() -> anyhow::Result<()> {
             let file_stem = input_path.file_stem().unwrap().to_string_lossy();
             let parent_dir = input_path.parent().unwrap();
 
-            // 2. Transcribe
-            println!("Transcribing...");
+            let transcript_path = parent_dir.join(format!("{}.transcript.json", file_stem));
+            let checkpoint_path =
+                parent_dir.join(format!("{}.transcript.checkpoint.json", file_stem));
+            let source_path = parent_dir.join(format!("{}.transcript.source.json", file_stem));
 
             // Get model config for the specified language
             let model_config = app_config.transcription.models.get(&lang).ok_or_else(|| {
                 anyhow::anyhow!("No transcription model configured for language: {:?}", lang)
             })?;
 
-            let mut pb = indicatif::ProgressBar::new(100);
-            pb.set_style(
-                indicatif::ProgressStyle::default_bar()
-                    .template(
-                        "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}% ({eta})",
-                    )
-                    .unwrap()
-                    .progress_chars("#>-"),
-            );
-
-            let whisper_conf = match &run_config {
+            let mut whisper_conf = match &run_config {
                 Some(config) => match &config.whisper {
                     Some(conf) => conf.clone(),
                     None => WhisperConfig::default(),
                 },
                 None => WhisperConfig::default(),
             };
+            if audio_stream.is_some() {
+                whisper_conf.audio_stream = audio_stream;
+            }
+            if audio_channel.is_some() {
+                whisper_conf.audio_channel = audio_channel;
+            }
 
-            let segments = match &model_config.engine {
-                TranscriptionEngine::WhisperCpp => {
-                    let mut whisper = Whisper::new(&app_config.transcription, lang.clone())
-                        .await
-                        .context("Failed to create Whisper instance")?;
-
-                    whisper
-                        .transcribe(&input_path, &whisper_conf, &mut pb)
-                        .context("Failed to transcribe with WhisperCpp")?
-                }
-                #[cfg(feature = "apple")]
-                TranscriptionEngine::Whisperkit => {
-                    let lang_str = if lang == Language::Auto {
-                        None
-                    } else {
-                        Some(lang.as_str())
-                    };
-                    let model_path = model_config.resolve_model_path().await?;
-                    let whisperkit = WhisperKit::new(model_path.to_str().unwrap(), lang_str);
-                    whisperkit
-                        .transcribe(&input_path, &whisper_conf, &mut pb)
-                        .context("Failed to transcribe with WhisperKit")?
-                }
+            let source = TranscriptSource {
+                input: InputFingerprint::of(&input_path)?,
+                model: model_config.model.clone(),
+                lang,
+                audio_stream: whisper_conf.audio_stream.clone(),
+                audio_channel: whisper_conf.audio_channel.unwrap_or_default(),
+                range_start: range.start,
+                range_end: range.end,
             };
 
-            pb.finish_with_message("Transcription complete");
+            // A resumed run whose transcription had finished goes straight to
+            // translation, if the transcript was made from the same input and settings
+            let finished = resume && transcript_path.exists() && !checkpoint_path.exists();
+            let reusable =
+                finished && TranscriptSource::load(&source_path).as_ref() == Some(&source);
+            let segments = if reusable {
+                println!("Reusing finished transcript {:?}", transcript_path);
+                input::read_segments(&transcript_path)?
+            } else {
+                if finished {
+                    println!(
+                        "Transcript {:?} was made from a different input, model, language, audio track or range; transcribing again",
+                        transcript_path
+                    );
+                }
 
-            // Save Transcript
-            let transcript_path = parent_dir.join(format!("{}.transcript.json", file_stem));
-            output::save_transcript_json(&transcript_path, &segments)?;
-            println!("Saved transcript to {:?}", transcript_path);
+                // 2. Transcribe
+                println!("Transcribing...");
+
+                let mut pb = indicatif::ProgressBar::new(100);
+                pb.set_style(
+                    indicatif::ProgressStyle::default_bar()
+                        .template(
+                            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}% ({eta})",
+                        )
+                        .unwrap()
+                        .progress_chars("#>-"),
+                );
+
+                // Chunked runs save their progress so an interrupted run can be resumed
+                let checkpoint = checkpoint::CheckpointOptions {
+                    path: checkpoint_path.clone(),
+                    resume,
+                };
+
+                let segments = match &model_config.engine {
+                    TranscriptionEngine::WhisperCpp => {
+                        let mut whisper = Whisper::new(&app_config.transcription, lang)
+                            .await
+                            .context("Failed to create Whisper instance")?;
+
+                        whisper
+                            .transcribe(
+                                &input_path,
+                                &whisper_conf,
+                                &range,
+                                Some(&checkpoint),
+                                &mut pb,
+                            )
+                            .context("Failed to transcribe with WhisperCpp")?
+                    }
+                    #[cfg(feature = "apple")]
+                    TranscriptionEngine::Whisperkit => {
+                        if resume {
+                            anyhow::bail!("--resume is only supported with whisper_cpp");
+                        }
+                        let lang_str = if lang == Language::Auto {
+                            None
+                        } else {
+                            Some(lang.as_str())
+                        };
+                        let model_path = model_config.resolve_model_path().await?;
+                        let whisperkit = WhisperKit::new(model_path.to_str().unwrap(), lang_str);
+                        whisperkit
+                            .transcribe(&input_path, &whisper_conf, &range, &mut pb)
+                            .context("Failed to transcribe with WhisperKit")?
+                    }
+                };
+
+                pb.finish_with_message("Transcription complete");
+
+                // Drop or flag segments that look hallucinated
+                let segments = match &whisper_conf.hallucination {
+                    Some(conf) => {
+                        let (segments, flagged) =
+                            transcribe::hallucination::filter_hallucinations(segments, conf);
+                        report_hallucinations(&flagged, conf.action.unwrap_or_default());
+                        segments
+                    }
+                    None => segments,
+                };
+
+                // Save Transcript
+                output::save_transcript_json(&transcript_path, &segments)?;
+                checkpoint::save(&source, &source_path)?;
+                println!("Saved transcript to {:?}", transcript_path);
+                checkpoint::remove(&checkpoint_path)?;
+                segments
+            };
 
             // 3. Translate (if config present)
             if let Some(rc) = run_config {
+                let mut output_config = rc.output.unwrap_or_default();
+                output_args.apply(&mut output_config);
                 if let Some(tc) = rc.translation {
-                    println!("Translating...");
-                    let pb_trans = indicatif::ProgressBar::new(segments.len() as u64);
-                    pb_trans.set_style(
-                        indicatif::ProgressStyle::default_bar()
-                            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-                            .unwrap()
-                            .progress_chars("#>-"),
-                    );
-                    pb_trans.enable_steady_tick(Duration::from_millis(100));
-
-                    let translated_segments = translate::process_translation(
-                        &lang,
+                    let target_langs = tc.translate.target_langs()?;
+                    let brief = prepare_brief(
                         &tc.translate,
-                        tc.edit.as_ref(),
-                        segments,
+                        &target_langs,
                         &app_config,
-                        &pb_trans,
+                        &segments,
+                        &parent_dir.join(format!("{}.brief.json", file_stem)),
                     )
                     .await?;
-                    pb_trans.finish_with_message("Translation complete");
-
-                    // Save Translation
-                    let translation_path =
-                        parent_dir.join(format!("{}.translation.json", file_stem));
-                    output::save_translation_json(&translation_path, &translated_segments)?;
-                    println!("Saved translation to {:?}", translation_path);
-
-                    // Save SRT
-                    let srt_path = parent_dir.join(format!("{}.srt", file_stem));
-                    output::save_srt(&srt_path, &translated_segments)?;
-                    println!("Saved SRT to {:?}", srt_path);
+                    let summary = prepare_summary(&tc.translate, &app_config, &segments).await?;
+
+                    for target_lang in &target_langs {
+                        // Several languages each get `<filename>.<lang>.*` outputs
+                        let lang_stem = if target_langs.len() > 1 {
+                            format!("{}.{}", file_stem, target_lang)
+                        } else {
+                            file_stem.to_string()
+                        };
+                        let translate_config = tc.translate.for_target(*target_lang);
+
+                        println!("Translating into {}...", target_lang);
+                        let pb_trans = indicatif::ProgressBar::new(segments.len() as u64);
+                        pb_trans.set_style(
+                            indicatif::ProgressStyle::default_bar()
+                                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
+                                .unwrap()
+                                .progress_chars("#>-"),
+                        );
+                        pb_trans.enable_steady_tick(Duration::from_millis(100));
+
+                        let translation_checkpoint = checkpoint::CheckpointOptions {
+                            path: parent_dir
+                                .join(format!("{}.translation.checkpoint.json", lang_stem)),
+                            resume,
+                        };
+                        let options = translate::TranslationOptions {
+                            checkpoint: Some(translation_checkpoint.clone()),
+                            brief: brief.clone(),
+                            memory_path: memory_path(no_cache)?,
+                            summary: summary.clone(),
+                        };
+                        let translated_segments = translate::process_translation(
+                            &lang,
+                            &translate_config,
+                            tc.edit.as_ref(),
+                            segments.clone(),
+                            &app_config,
+                            &options,
+                            &pb_trans,
+                        )
+                        .await?;
+                        pb_trans.finish_with_message("Translation complete");
+
+                        // Save Translation
+                        let translation_path =
+                            parent_dir.join(format!("{}.translation.json", lang_stem));
+                        output::save_translation_json(&translation_path, &translated_segments)?;
+                        println!("Saved translation to {:?}", translation_path);
+
+                        // Save Subtitles
+                        save_subtitles(
+                            parent_dir,
+                            &lang_stem,
+                            &input_path,
+                            target_lang,
+                            &translated_segments,
+                            &output_config,
+                        )?;
+                        checkpoint::remove(&translation_checkpoint.path)?;
+                    }
                 }
             }
         }
@@ -191,6 +534,9 @@ async fn main() -> anyhow::Result<()> {
             input,
             profile,
             lang,
+            resume,
+            no_cache,
+            output: output_args,
         } => {
             println!("Translating from transcript: {:?}", input);
 
@@ -200,75 +546,123 @@ async fn main() -> anyhow::Result<()> {
             // 2. Load Run Config (Required)
             let conf_path = resolve_profile_path(&profile)?;
             let run_config = config::load_run_config(&conf_path)?;
+            let mut output_config = run_config.output.unwrap_or_default();
+            output_args.apply(&mut output_config);
             let tc = run_config.translation.ok_or_else(|| {
                 anyhow::anyhow!("Translation config is required for translate command")
             })?;
 
             // 3. Load Transcript
-            let transcript_content = std::fs::read_to_string(&input)?;
-            let segments: Vec<transcribe::TranscriptSegment> =
-                serde_json::from_str(&transcript_content)?;
+            let input_format = input::InputFormat::from_path(&input)?;
+            let segments = input::read_segments(&input)?;
 
             if segments.is_empty() {
                 anyhow::bail!("Transcript is empty");
             }
 
-            // 4. Translate
-            println!("Translating...");
-            let pb_trans = ProgressBar::new(segments.len() as u64);
-            pb_trans.set_style(
-                ProgressStyle::default_bar()
-                    .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-                    .unwrap()
-                    .progress_chars("#>-"),
-            );
-            pb_trans.enable_steady_tick(Duration::from_millis(100));
-
-            let translated_segments = translate::process_translation(
-                &lang,
-                &tc.translate,
-                tc.edit.as_ref(),
-                segments,
-                &app_config,
-                &pb_trans,
-            )
-            .await?;
-            pb_trans.finish_with_message("Translation complete");
-
-            // 5. Save Outputs
             let input_path = Path::new(&input);
             let raw_stem = input_path
                 .file_stem()
                 .and_then(|s| s.to_str())
                 .unwrap_or("output");
-
-            let file_stem = if raw_stem.ends_with(".transcript") {
-                raw_stem.trim_end_matches(".transcript")
+            let is_transcript = matches!(input_format, input::InputFormat::TranscriptJson);
+            let file_stem = if is_transcript {
+                raw_stem.strip_suffix(".transcript").unwrap_or(raw_stem)
             } else {
                 raw_stem
             };
             let parent = input_path.parent().unwrap_or_else(|| Path::new("."));
 
-            // Save JSON
-            let output_json_path = parent.join(format!("{}.translation.json", file_stem));
-            let json_file = std::fs::File::create(&output_json_path)?;
-            serde_json::to_writer_pretty(json_file, &translated_segments)?;
-            println!("Saved translation to {:?}", output_json_path);
-
-            // Save SRT
-            let output_srt_path = parent.join(format!("{}.srt", file_stem));
-            let mut srt_file = std::fs::File::create(&output_srt_path)?;
-            for (i, segment) in translated_segments.iter().enumerate() {
-                writeln!(
-                    srt_file,
-                    "{}\n{} --> {}\n{}\n",
-                    i + 1,
-                    output::format_timestamp(segment.start),
-                    output::format_timestamp(segment.end),
-                    segment.translated
+            let target_langs = tc.translate.target_langs()?;
+            let brief = prepare_brief(
+                &tc.translate,
+                &target_langs,
+                &app_config,
+                &segments,
+                &parent.join(format!("{}.brief.json", file_stem)),
+            )
+            .await?;
+            let summary = prepare_summary(&tc.translate, &app_config, &segments).await?;
+
+            for target_lang in &target_langs {
+                // Keep the source subtitles from being overwritten by the translated
+                // ones, and give each of several languages its own outputs
+                let lang_stem = if is_transcript && target_langs.len() == 1 {
+                    file_stem.to_string()
+                } else {
+                    format!("{}.{}", file_stem, target_lang)
+                };
+                let translate_config = tc.translate.for_target(*target_lang);
+
+                // 4. Translate
+                println!("Translating into {}...", target_lang);
+                let pb_trans = ProgressBar::new(segments.len() as u64);
+                pb_trans.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
+                        .unwrap()
+                        .progress_chars("#>-"),
+                );
+                pb_trans.enable_steady_tick(Duration::from_millis(100));
+
+                let checkpoint = checkpoint::CheckpointOptions {
+                    path: parent.join(format!("{}.translation.checkpoint.json", lang_stem)),
+                    resume,
+                };
+                let options = translate::TranslationOptions {
+                    checkpoint: Some(checkpoint.clone()),
+                    brief: brief.clone(),
+                    memory_path: memory_path(no_cache)?,
+                    summary: summary.clone(),
+                };
+                let translated_segments = translate::process_translation(
+                    &lang,
+                    &translate_config,
+                    tc.edit.as_ref(),
+                    segments.clone(),
+                    &app_config,
+                    &options,
+                    &pb_trans,
+                )
+                .await?;
+                pb_trans.finish_with_message("Translation complete");
+
+                // 5. Save Outputs
+                // Save JSON
+                let output_json_path = parent.join(format!("{}.translation.json", lang_stem));
+                output::save_translation_json(&output_json_path, &translated_segments)?;
+                println!("Saved translation to {:?}", output_json_path);
+
+                // Save Subtitles
+                save_subtitles(
+                    parent,
+                    &lang_stem,
+                    input_path,
+                    target_lang,
+                    &translated_segments,
+                    &output_config,
                 )?;
+                checkpoint::remove(&checkpoint.path)?;
+            }
+        }
+        Commands::Cache { command } => {
+            let path = TranslationMemory::default_path()?;
+            match command {
+                CacheCommand::Clear => {
+                    checkpoint::remove(&path)?;
+                    println!("Cleared translation memory {:?}", path);
+                }
+                CacheCommand::Export { output } => {
+                    let memory = TranslationMemory::open(&path)?;
+                    memory.export(&output)?;
+                    println!(
+                        "Exported {} translations from {:?} to {:?}",
+                        memory.len(),
+                        path,
+                        output
+                    );
+                }
             }
-            println!("Saved SRT to {:?}", output_srt_path);
         }
     }
 
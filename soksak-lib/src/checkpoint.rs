@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+
+/// Where to keep a pipeline's progress, and whether to pick up from it.
+#[derive(Debug, Clone)]
+pub struct CheckpointOptions {
+    pub path: PathBuf,
+    pub resume: bool,
+}
+
+/// Loads the JSON file at `path`, or `None` if there is none.
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<Option<T>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    let value =
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))?;
+    Ok(Some(value))
+}
+
+/// Writes `value` as JSON to a temporary file first, so an interruption never
+/// leaves a truncated file behind.
+pub fn save<T: Serialize>(value: &T, path: &Path) -> Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, serde_json::to_string_pretty(value)?)
+        .with_context(|| format!("Failed to write {:?}", temp_path))?;
+    std::fs::rename(&temp_path, path).with_context(|| format!("Failed to write {:?}", path))
+}
+
+/// Removes the file at `path`, if any, such as a checkpoint once its output
+/// has been saved.
+pub fn remove(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {:?}", path))
+        }
+        _ => Ok(()),
+    }
+}
@@ -3,8 +3,9 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
 pub enum Language {
+    #[default]
     Auto,
     English,
     Chinese,
@@ -347,7 +348,7 @@ impl From<&str> for Language {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct AppConfig {
     pub transcription: TranscriptionConfig,
     pub llm: LlmConfig,
@@ -436,7 +437,7 @@ pub struct TranscriptionConfig {
     pub models: HashMap<Language, TranscriptionModelConfig>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct LlmConfig {
     pub providers: Vec<LlmProviderConfig>,
 }
@@ -447,6 +448,9 @@ pub enum ApiType {
     Ollama,
     Claude,
     Gemini,
+    /// A provider added with `llm::register_provider` under this name
+    #[serde(untagged)]
+    Custom(String),
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
@@ -465,12 +469,18 @@ pub struct LlmProviderConfig {
     pub api_type: ApiType,
     #[serde(default)]
     pub json_mode_type: JsonModeType,
+    pub timeout: Option<f32>,     // seconds per request, default: 300
+    pub max_retries: Option<u32>, // on rate limits, server errors and timeouts, default: 5
+    pub requests_per_minute: Option<u32>, // default: unlimited
+    pub tokens_per_minute: Option<u32>, // estimated from request length, default: unlimited
+    pub max_input_tokens: Option<u32>, // estimated from request length, default: 100000
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RunConfig {
     pub whisper: Option<WhisperConfig>,
     pub translation: Option<TranslationConfig>,
+    pub output: Option<OutputConfig>,
 }
 
 #[derive(Debug, Deserialize, Default, Clone)]
@@ -480,6 +490,95 @@ pub struct WhisperConfig {
     pub initial_prompt: Option<String>,
     pub vad: Option<bool>,
     pub temperature: Option<f32>,
+    pub word_timestamps: Option<bool>, // whisper_cpp only
+    pub hallucination: Option<HallucinationConfig>,
+    pub audio_stream: Option<AudioStream>, // default: the input's default audio stream
+    pub audio_channel: Option<AudioChannel>, // default: mix
+    pub chunk: Option<ChunkConfig>,        // whisper_cpp only
+}
+
+/// Transcribes long inputs window by window, cut at silences, so memory use
+/// stays bounded by the window length instead of the input length and
+/// progress can be saved after every window.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct ChunkConfig {
+    pub enabled: Option<bool>, // default: true
+    pub length: Option<u32>,   // seconds, default: 600
+    pub overlap: Option<f32>,  // seconds re-transcribed when no silence is found, default: 2.0
+}
+
+/// Audio stream to transcribe, by its position among the audio streams
+/// (`0` is the first) or by its language tag (e.g. `jpn`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum AudioStream {
+    Index(usize),
+    Language(String),
+}
+
+impl std::str::FromStr for AudioStream {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            anyhow::bail!("Audio stream must be an index or a language tag");
+        }
+        Ok(match s.parse::<usize>() {
+            Ok(index) => AudioStream::Index(index),
+            Err(_) => AudioStream::Language(s.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for AudioStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioStream::Index(index) => write!(f, "#{}", index),
+            AudioStream::Language(lang) => write!(f, "language {:?}", lang),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioChannel {
+    #[default]
+    Mix,
+    Left,
+    Right,
+}
+
+impl std::str::FromStr for AudioChannel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mix" | "mono" => Ok(AudioChannel::Mix),
+            "left" | "l" => Ok(AudioChannel::Left),
+            "right" | "r" => Ok(AudioChannel::Right),
+            _ => anyhow::bail!("Unknown audio channel: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HallucinationAction {
+    #[default]
+    Drop,
+    Flag,
+}
+
+/// Post-filter for segments that look hallucinated (repetition loops, text over silence).
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct HallucinationConfig {
+    pub action: Option<HallucinationAction>,      // default: drop
+    pub no_speech_threshold: Option<f32>,         // default: 0.6
+    pub logprob_threshold: Option<f32>,           // default: -1.0
+    pub compression_ratio_threshold: Option<f32>, // default: 2.4
+    pub max_repeats: Option<usize>,               // consecutive identical lines kept, default: 2
+    pub phrases: Option<Vec<String>>,             // extra known hallucination phrases
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -488,7 +587,9 @@ pub enum TranslateEngine {
     LLM {
         model: String, // {provider_id}/{model}
         system_prompt: Option<String>,
-        window: Option<usize>, // default size: 100
+        window: Option<usize>,        // default size: 100
+        repair_attempts: Option<u32>, // default: 3
+        concurrency: Option<usize>,   // default: 1
     },
     #[cfg(feature = "apple")]
     Apple {
@@ -499,7 +600,77 @@ pub enum TranslateEngine {
 #[derive(Debug, Deserialize, Clone)]
 pub struct Translate {
     pub engine: TranslateEngine,
-    pub target_lang: Language,
+    #[serde(default)]
+    pub target_lang: Language, // unless target_langs is given
+    pub target_langs: Option<Vec<Language>>,
+    pub brief: Option<BriefConfig>,
+    pub glossary: Option<GlossaryConfig>,
+    pub resegment: Option<ResegmentConfig>,
+}
+
+impl Translate {
+    /// The languages to translate into: `target_langs`, or else `target_lang`.
+    pub fn target_langs(&self) -> anyhow::Result<Vec<Language>> {
+        let langs = match &self.target_langs {
+            Some(langs) => {
+                let mut unique = Vec::new();
+                for lang in langs {
+                    if !unique.contains(lang) {
+                        unique.push(*lang);
+                    }
+                }
+                unique
+            }
+            None => vec![self.target_lang],
+        };
+        if langs.is_empty() || langs.contains(&Language::Auto) {
+            anyhow::bail!(
+                "translation.translate needs a target_lang or target_langs with known language codes"
+            );
+        }
+        Ok(langs)
+    }
+
+    /// This configuration translating into `lang` only.
+    pub fn for_target(&self, lang: Language) -> Self {
+        Self {
+            target_lang: lang,
+            target_langs: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// Terms to translate consistently, listed in the profile or kept in a
+/// CSV/TSV file (relative paths are resolved against the profile's directory).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum GlossaryConfig {
+    Path(PathBuf),
+    Entries(Vec<GlossaryEntry>),
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct GlossaryEntry {
+    pub source: String,
+    pub target: Option<String>,         // required unless do_not_translate
+    pub case_sensitive: Option<bool>,   // default: false
+    pub do_not_translate: Option<bool>, // default: false
+}
+
+/// Reads the whole transcript once before translating and writes a brief
+/// (topic, speakers, tone, key terms) that every window is translated with.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct BriefConfig {
+    pub model: Option<String>, // {provider_id}/{model}, default: the translation model
+}
+
+/// Merges transcript fragments into whole sentences before translating, then
+/// splits each translated sentence back over the fragments' time spans.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct ResegmentConfig {
+    pub max_chars: Option<usize>, // longest merged sentence, default: 300
+    pub max_gap: Option<f32>,     // seconds of silence that end a sentence, default: 1.5
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -522,6 +693,151 @@ pub struct FilterConfig {
     pub llm: Option<String>, // Optional specific LLM for this filter
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+    Ass,
+}
+
+impl SubtitleFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Ass => "ass",
+        }
+    }
+}
+
+impl std::str::FromStr for SubtitleFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "srt" => Ok(SubtitleFormat::Srt),
+            "vtt" | "webvtt" => Ok(SubtitleFormat::Vtt),
+            "ass" | "ssa" => Ok(SubtitleFormat::Ass),
+            _ => anyhow::bail!("Unknown subtitle format: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct OutputConfig {
+    pub formats: Option<Vec<SubtitleFormat>>, // default: [srt]
+    pub vtt: Option<VttConfig>,
+    pub ass: Option<AssConfig>,
+    pub bilingual: Option<BilingualConfig>, // SRT/VTT only; ASS has its own styled lines
+    pub layout: Option<LayoutConfig>,
+}
+
+impl OutputConfig {
+    pub fn formats(&self) -> Vec<SubtitleFormat> {
+        match &self.formats {
+            Some(formats) if !formats.is_empty() => formats.clone(),
+            _ => vec![SubtitleFormat::Srt],
+        }
+    }
+}
+
+/// Line wrapping and reading-speed constraints for subtitle cues.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct LayoutConfig {
+    #[serde(flatten)]
+    pub rules: LayoutRulesConfig,
+    pub languages: Option<HashMap<Language, LayoutRulesConfig>>, // per target language overrides
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct LayoutRulesConfig {
+    pub max_chars_per_line: Option<usize>,
+    pub max_lines: Option<usize>,
+    pub max_cps: Option<f32>,      // characters per second
+    pub min_duration: Option<f32>, // seconds
+    pub max_duration: Option<f32>, // seconds
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BilingualOrder {
+    #[default]
+    TranslatedFirst,
+    OriginalFirst,
+}
+
+impl std::str::FromStr for BilingualOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "_").as_str() {
+            "translated_first" | "translated" => Ok(BilingualOrder::TranslatedFirst),
+            "original_first" | "original" => Ok(BilingualOrder::OriginalFirst),
+            _ => anyhow::bail!("Unknown bilingual order: {}", s),
+        }
+    }
+}
+
+/// Shows both the translated and the original text in each cue.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct BilingualConfig {
+    pub order: Option<BilingualOrder>, // default: translated_first
+    pub separator: Option<String>,     // default: "\n" (separate lines)
+}
+
+/// WebVTT cue settings applied to every cue, e.g. `line: "85%"`.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct VttConfig {
+    pub position: Option<String>,
+    pub line: Option<String>,
+    pub align: Option<String>,
+    pub size: Option<String>,
+}
+
+impl VttConfig {
+    pub fn cue_settings(&self) -> String {
+        [
+            ("position", &self.position),
+            ("line", &self.line),
+            ("align", &self.align),
+            ("size", &self.size),
+        ]
+        .iter()
+        .filter_map(|(key, value)| value.as_ref().map(|v| format!("{}:{}", key, v)))
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct AssConfig {
+    pub play_res_x: Option<u32>,     // default: 1920
+    pub play_res_y: Option<u32>,     // default: 1080
+    pub show_original: Option<bool>, // default: true
+    pub translated_style: Option<AssStyle>,
+    pub original_style: Option<AssStyle>,
+}
+
+/// Overrides for an ASS `Style:` line. Colours are either ASS literals
+/// (`&H00FFFFFF`) or `#RRGGBB`/`#RRGGBBAA`.
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct AssStyle {
+    pub font_name: Option<String>,
+    pub font_size: Option<u32>,
+    pub primary_colour: Option<String>,
+    pub outline_colour: Option<String>,
+    pub back_colour: Option<String>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub outline: Option<f32>,
+    pub shadow: Option<f32>,
+    pub alignment: Option<u8>, // numpad layout: 2 = bottom center, 8 = top center
+    pub margin_l: Option<u32>,
+    pub margin_r: Option<u32>,
+    pub margin_v: Option<u32>,
+}
+
 pub fn load_app_config() -> anyhow::Result<AppConfig> {
     let home = dirs::home_dir().context("Could not find home directory")?;
     let config_path = home.join(".soksak/config.yaml");
@@ -537,6 +853,18 @@ pub fn load_app_config() -> anyhow::Result<AppConfig> {
 
 pub fn load_run_config(path: &PathBuf) -> anyhow::Result<RunConfig> {
     let content = std::fs::read_to_string(path)?;
-    let config: RunConfig = serde_yaml::from_str(&content)?;
+    let mut config: RunConfig = serde_yaml::from_str(&content)?;
+
+    let glossary = config
+        .translation
+        .as_mut()
+        .and_then(|t| t.translate.glossary.as_mut());
+    if let Some(GlossaryConfig::Path(glossary_path)) = glossary {
+        if glossary_path.is_relative() {
+            if let Some(dir) = path.parent() {
+                *glossary_path = dir.join(&*glossary_path);
+            }
+        }
+    }
     Ok(config)
 }
@@ -1,61 +1,513 @@
-use anyhow::{Result, anyhow};
-use audrey::Reader;
-use std::path::Path;
+use crate::config::{AudioChannel, AudioStream, WhisperConfig};
+use anyhow::{Context, Result, anyhow, bail};
+use ffmpeg_sidecar::command::FfmpegCommand;
+use ffmpeg_sidecar::event::{FfmpegEvent, LogLevel};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::process::Stdio;
 use tempfile::NamedTempFile;
 
-// ffmpeg -i input.mp3 -ar 16000 output.wav
-fn use_ffmpeg<P: AsRef<Path>>(input_path: P) -> Result<NamedTempFile> {
-    println!("Using ffmpeg to convert audio file");
+/// A span of the input to decode, in centiseconds. `None` means the
+/// beginning or the end of the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+impl TimeRange {
+    pub fn new(start: Option<i64>, end: Option<i64>) -> Result<Self> {
+        if let (Some(start), Some(end)) = (start, end) {
+            if end <= start {
+                bail!("end of time range must be after its start");
+            }
+        }
+        Ok(Self { start, end })
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+
+    /// Where decoded timestamps begin on the original timeline.
+    pub fn offset(&self) -> i64 {
+        self.start.unwrap_or(0)
+    }
+
+    fn ffmpeg_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(start) = self.start {
+            args.extend(["-ss".to_string(), format_seconds(start)]);
+        }
+        if let Some(end) = self.end {
+            // `-ss` before `-i` resets the timeline, so give a duration
+            args.extend(["-t".to_string(), format_seconds(end - self.offset())]);
+        }
+        args
+    }
+}
+
+/// Which audio stream and channel of the input to transcribe.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AudioSelection {
+    pub stream: Option<AudioStream>,
+    pub channel: AudioChannel,
+}
+
+impl AudioSelection {
+    pub fn from_config(conf: &WhisperConfig) -> Self {
+        Self {
+            stream: conf.audio_stream.clone(),
+            channel: conf.audio_channel.unwrap_or_default(),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioStreamInfo {
+    pub index: usize, // among all streams of the input
+    pub codec_name: Option<String>,
+    pub channels: Option<u32>,
+    #[serde(default)]
+    pub tags: AudioStreamTags,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AudioStreamTags {
+    pub language: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<AudioStreamInfo>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>, // seconds, as a decimal string
+}
 
-    let temp_file = NamedTempFile::with_suffix(".wav")?;
-    let temp_path = temp_file.path();
+impl ProbeOutput {
+    /// Duration of the input in centiseconds, if ffprobe knows it.
+    fn duration(&self) -> Option<i64> {
+        let seconds: f64 = self.format.as_ref()?.duration.as_ref()?.parse().ok()?;
+        Some((seconds * 100.0).round() as i64)
+    }
+}
 
-    let mut pid = Command::new("ffmpeg")
+fn probe<P: AsRef<Path>>(input_path: P) -> Result<ProbeOutput> {
+    let output = Command::new("ffprobe")
         .args([
-            "-i",
-            input_path
-                .as_ref()
-                .to_str()
-                .ok_or_else(|| anyhow!("invalid path"))?,
-            "-ar",
-            "16000",
-            "-ac",
-            "1",
-            "-c:a",
-            "pcm_s16le",
-            temp_path.to_str().unwrap(),
-            "-hide_banner",
-            "-y",
-            "-loglevel",
+            "-v",
             "error",
+            "-select_streams",
+            "a",
+            "-show_entries",
+            "stream=index,codec_name,channels:stream_tags=language,title:format=duration",
+            "-of",
+            "json",
         ])
+        .arg(input_path.as_ref())
         .stdin(Stdio::null())
-        .spawn()?;
+        .output()
+        .context("Failed to run ffprobe")?;
+
+    if !output.status.success() {
+        bail!(
+            "ffprobe failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    serde_json::from_slice(&output.stdout).context("Failed to parse ffprobe output")
+}
+
+/// Lists the audio streams of the input with ffprobe.
+pub fn probe_audio_streams<P: AsRef<Path>>(input_path: P) -> Result<Vec<AudioStreamInfo>> {
+    Ok(probe(input_path)?.streams)
+}
+
+/// Formats streams as a list for error messages, numbered the way
+/// `audio_stream` counts them.
+pub fn describe_streams(streams: &[AudioStreamInfo]) -> String {
+    streams
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let mut line = format!(
+                "  {}: language={}, codec={}, channels={}",
+                i,
+                s.tags.language.as_deref().unwrap_or("und"),
+                s.codec_name.as_deref().unwrap_or("?"),
+                s.channels.map(|c| c.to_string()).unwrap_or("?".to_string()),
+            );
+            if let Some(title) = &s.tags.title {
+                line.push_str(&format!(" ({})", title));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Resolves the selection to a position among the audio streams.
+fn select_stream(streams: &[AudioStreamInfo], selection: &AudioStream) -> Result<usize> {
+    let position = match selection {
+        AudioStream::Index(index) => {
+            if *index >= streams.len() {
+                bail!(
+                    "Audio stream {} does not exist; available streams:\n{}",
+                    selection,
+                    describe_streams(streams)
+                );
+            }
+            *index
+        }
+        AudioStream::Language(lang) => {
+            let matches: Vec<usize> = streams
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| {
+                    s.tags
+                        .language
+                        .as_deref()
+                        .is_some_and(|l| l.eq_ignore_ascii_case(lang))
+                })
+                .map(|(i, _)| i)
+                .collect();
+            match matches.as_slice() {
+                [position] => *position,
+                [] => bail!(
+                    "No audio stream with {}; available streams:\n{}",
+                    selection,
+                    describe_streams(streams)
+                ),
+                _ => bail!(
+                    "Several audio streams match {}, choose one by index:\n{}",
+                    selection,
+                    describe_streams(streams)
+                ),
+            }
+        }
+    };
+
+    Ok(position)
+}
+
+/// ffmpeg output options that pick the selected stream and channel.
+/// `streams` is `None` when the input could not be probed.
+fn audio_args(
+    streams: Option<&[AudioStreamInfo]>,
+    selection: &AudioSelection,
+) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+
+    match (&selection.stream, streams) {
+        (Some(stream), Some(streams)) => {
+            let position = select_stream(streams, stream)?;
+            if selection.channel != AudioChannel::Mix && streams[position].channels.unwrap_or(2) < 2
+            {
+                bail!("Audio stream {} has a single channel", stream);
+            }
+            args.extend(["-map".to_string(), format!("0:a:{}", position)]);
+        }
+        (Some(stream), None) => bail!("Cannot select audio stream {}: ffprobe failed", stream),
+        (None, Some(streams)) if streams.len() > 1 => {
+            println!(
+                "Input has {} audio streams, using the default one (choose with --audio-stream):\n{}",
+                streams.len(),
+                describe_streams(streams)
+            );
+        }
+        (None, _) => {}
+    }
+
+    match selection.channel {
+        AudioChannel::Mix => {}
+        AudioChannel::Left => args.extend(["-af".to_string(), "pan=mono|c0=FL".to_string()]),
+        AudioChannel::Right => args.extend(["-af".to_string(), "pan=mono|c0=FR".to_string()]),
+    }
+
+    Ok(args)
+}
+
+/// Parses `[hh:]mm:ss[.fff]` or plain seconds (`2400`, `2400.5`) into centiseconds.
+pub fn parse_time(s: &str) -> Result<i64> {
+    let invalid = || {
+        anyhow!(
+            "invalid time {:?} (expected [hh:]mm:ss[.fff] or seconds)",
+            s
+        )
+    };
+
+    let parts: Vec<&str> = s.trim().split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+
+    let (last, clock) = parts.split_last().ok_or_else(invalid)?;
+    let seconds: f64 = last.parse().map_err(|_| invalid())?;
+    if seconds < 0.0 || (!clock.is_empty() && seconds >= 60.0) {
+        return Err(invalid());
+    }
+
+    let mut total = 0i64;
+    for (i, part) in clock.iter().enumerate() {
+        let value: i64 = part.parse().map_err(|_| invalid())?;
+        // minutes must stay below 60 when hours are given
+        if value < 0 || (i > 0 && value >= 60) {
+            return Err(invalid());
+        }
+        total = total * 60 + value;
+    }
+
+    Ok(total * 6000 + (seconds * 100.0).round() as i64)
+}
+
+fn format_seconds(cs: i64) -> String {
+    format!("{}.{:02}", cs / 100, cs % 100)
+}
+
+pub const SAMPLE_RATE: usize = 16000;
+
+/// An input opened for decoding. The audio stream and channel are resolved
+/// once, so ranges of it can be decoded repeatedly.
+pub struct AudioSource {
+    path: PathBuf,
+    audio_args: Vec<String>,
+    duration: Option<i64>, // centiseconds
+}
+
+impl AudioSource {
+    pub fn open<P: AsRef<Path>>(input_path: P, audio: &AudioSelection) -> Result<Self> {
+        let path = input_path.as_ref().to_path_buf();
+        let probe = match probe(&path) {
+            Ok(probe) => Some(probe),
+            Err(e) if audio.stream.is_some() => return Err(e),
+            Err(_) => None,
+        };
+
+        let audio_args = audio_args(probe.as_ref().map(|p| &p.streams[..]), audio)?;
+        let duration = probe.as_ref().and_then(ProbeOutput::duration);
+
+        Ok(Self {
+            path,
+            audio_args,
+            duration,
+        })
+    }
+
+    /// Duration of the input in centiseconds, if known.
+    pub fn duration(&self) -> Option<i64> {
+        self.duration
+    }
+
+    // ffmpeg [-ss start -t duration] -i input.mp3 [-map 0:a:N] [-af pan=...] -ar 16000 -ac 1 <output>
+    fn command(&self, range: &TimeRange) -> Result<FfmpegCommand> {
+        let mut command = FfmpegCommand::new();
+        command
+            .hide_banner()
+            .args(range.ffmpeg_args())
+            .input(self.path.to_str().ok_or_else(|| anyhow!("invalid path"))?)
+            .no_video()
+            .args(&self.audio_args)
+            .args(["-ar", &SAMPLE_RATE.to_string(), "-ac", "1"]);
+        Ok(command)
+    }
+
+    /// Decodes `range` into 16kHz mono samples, streamed from ffmpeg's stdout.
+    /// Returns no samples for a range past the end of the input.
+    pub fn read(&self, range: &TimeRange) -> Result<Vec<f32>> {
+        let mut command = self.command(range)?;
+        command
+            .format("f32le")
+            .codec_audio("pcm_f32le")
+            .pipe_stdout();
+
+        let mut samples = Vec::new();
+        let mut pending = Vec::with_capacity(4);
+        run_ffmpeg(&mut command, &self.path, |chunk| {
+            decode_f32le(&mut pending, chunk, &mut samples)
+        })?;
+
+        Ok(samples)
+    }
+
+    /// Writes `range` to a temporary 16kHz mono WAV, for engines that read
+    /// the file themselves.
+    pub fn to_wav(&self, range: &TimeRange) -> Result<NamedTempFile> {
+        let temp_file = NamedTempFile::with_suffix(".wav")?;
+
+        let mut command = self.command(range)?;
+        command
+            .codec_audio("pcm_s16le")
+            .overwrite()
+            .output(temp_file.path().to_str().unwrap());
+        run_ffmpeg(&mut command, &self.path, |_| {})?;
 
-    if pid.wait()?.success() {
-        println!("Audio file converted successfully");
         Ok(temp_file)
-    } else {
-        Err(anyhow!("unable to convert file"))
     }
 }
 
-pub fn read_file<P: AsRef<Path>>(audio_file_path: P) -> Result<Vec<f32>> {
-    let temp_file = use_ffmpeg(&audio_file_path)?;
+/// Runs ffmpeg to completion, passing raw stdout chunks to `on_chunk`.
+/// Fails with ffmpeg's own error messages if it exits unsuccessfully.
+fn run_ffmpeg(
+    command: &mut FfmpegCommand,
+    input_path: &Path,
+    mut on_chunk: impl FnMut(&[u8]),
+) -> Result<()> {
+    let mut child = command.spawn().context("Failed to run ffmpeg")?;
+
+    let mut errors = Vec::new();
+    for event in child.iter()? {
+        match event {
+            FfmpegEvent::OutputChunk(chunk) => on_chunk(&chunk),
+            FfmpegEvent::Log(LogLevel::Error | LogLevel::Fatal, message)
+            | FfmpegEvent::Error(message) => errors.push(message),
+            _ => {}
+        }
+    }
 
-    let mut reader = Reader::new(temp_file.reopen()?)?;
-    let audio_buf: Vec<i16> = reader.samples().collect::<Result<_, _>>()?;
-    let mut output = vec![0.0f32; audio_buf.len()];
+    let status = child.wait()?;
+    if !status.success() {
+        let details = if errors.is_empty() {
+            status.to_string()
+        } else {
+            errors.join("\n")
+        };
+        bail!("ffmpeg failed to decode {:?}:\n{}", input_path, details);
+    }
 
-    whisper_rs::convert_integer_to_float_audio(&audio_buf, &mut output)?;
-    Ok(output)
-    // temp_file is automatically deleted when it goes out of scope here
+    Ok(())
+}
+
+/// Appends little-endian f32 samples from `chunk` to `out`. Pipe reads are not
+/// aligned to samples, so a partial sample is kept in `pending` for the next chunk.
+fn decode_f32le(pending: &mut Vec<u8>, chunk: &[u8], out: &mut Vec<f32>) {
+    let mut chunk = chunk;
+    if !pending.is_empty() {
+        let needed = (4 - pending.len()).min(chunk.len());
+        pending.extend_from_slice(&chunk[..needed]);
+        chunk = &chunk[needed..];
+        if pending.len() < 4 {
+            return;
+        }
+        out.push(f32::from_le_bytes([
+            pending[0], pending[1], pending[2], pending[3],
+        ]));
+        pending.clear();
+    }
+
+    let samples = chunk.chunks_exact(4);
+    pending.extend_from_slice(samples.remainder());
+    out.extend(samples.map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+}
+
+/// Decodes `range` of the selected audio into 16kHz mono samples.
+pub fn read_file<P: AsRef<Path>>(
+    audio_file_path: P,
+    range: &TimeRange,
+    audio: &AudioSelection,
+) -> Result<Vec<f32>> {
+    let source = AudioSource::open(&audio_file_path, audio)?;
+
+    println!("Decoding audio with ffmpeg");
+    let samples = source.read(range)?;
+    if samples.is_empty() {
+        bail!("No audio decoded from {:?}", audio_file_path.as_ref());
+    }
+    println!("Audio decoded successfully");
+
+    Ok(samples)
 }
 
 #[allow(dead_code)]
-pub fn file<P: AsRef<Path>>(audio_file_path: P) -> Result<NamedTempFile> {
-    let temp_file = use_ffmpeg(&audio_file_path)?;
+pub fn file<P: AsRef<Path>>(
+    audio_file_path: P,
+    range: &TimeRange,
+    audio: &AudioSelection,
+) -> Result<NamedTempFile> {
+    let source = AudioSource::open(&audio_file_path, audio)?;
+
+    println!("Using ffmpeg to convert audio file");
+    let temp_file = source.to_wav(range)?;
+    println!("Audio file converted successfully");
+
     Ok(temp_file)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("2400").unwrap(), 240000);
+        assert_eq!(parse_time("1.25").unwrap(), 125);
+        assert_eq!(parse_time("40:00").unwrap(), 240000);
+        assert_eq!(parse_time("1:35:00.5").unwrap(), 570050);
+        assert!(parse_time("1:60").is_err());
+        assert!(parse_time("abc").is_err());
+    }
+
+    #[test]
+    fn test_range_args() {
+        let range = TimeRange::new(Some(240000), Some(570000)).unwrap();
+        assert_eq!(range.ffmpeg_args(), vec!["-ss", "2400.00", "-t", "3300.00"]);
+        assert!(TimeRange::new(Some(100), Some(100)).is_err());
+        assert!(TimeRange::default().ffmpeg_args().is_empty());
+    }
+
+    #[test]
+    fn test_decode_f32le_across_chunks() {
+        let bytes: Vec<u8> = [0.5f32, -1.0, 0.25]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let mut pending = Vec::new();
+        let mut out = Vec::new();
+
+        for chunk in [&bytes[..3], &bytes[3..5], &bytes[5..]] {
+            decode_f32le(&mut pending, chunk, &mut out);
+        }
+
+        assert_eq!(out, vec![0.5, -1.0, 0.25]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_select_stream() {
+        let stream = |language: &str| AudioStreamInfo {
+            index: 0,
+            codec_name: Some("aac".to_string()),
+            channels: Some(2),
+            tags: AudioStreamTags {
+                language: Some(language.to_string()),
+                title: None,
+            },
+        };
+        let streams = vec![stream("eng"), stream("jpn"), stream("jpn")];
+
+        assert_eq!(select_stream(&streams, &AudioStream::Index(2)).unwrap(), 2);
+        assert_eq!(
+            select_stream(&streams, &AudioStream::Language("ENG".to_string())).unwrap(),
+            0
+        );
+
+        let err = select_stream(&streams, &AudioStream::Language("jpn".to_string())).unwrap_err();
+        assert!(err.to_string().starts_with("Several audio streams match"));
+        assert!(
+            err.to_string()
+                .contains("  2: language=jpn, codec=aac, channels=2")
+        );
+        assert!(select_stream(&streams, &AudioStream::Index(3)).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::transcribe::TranscriptSegment;
+use anyhow::{Context, Result, anyhow, bail};
+use regex::Regex;
+use std::path::Path;
+use std::sync::LazyLock;
+
+static TAG_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>|\{\\[^}]*\}").unwrap());
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InputFormat {
+    TranscriptJson,
+    Srt,
+    Vtt,
+}
+
+impl InputFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match ext.as_str() {
+            "json" => Ok(InputFormat::TranscriptJson),
+            "srt" => Ok(InputFormat::Srt),
+            "vtt" => Ok(InputFormat::Vtt),
+            _ => bail!(
+                "Unsupported input file {:?}: expected .transcript.json, .srt or .vtt",
+                path
+            ),
+        }
+    }
+}
+
+/// Reads transcript segments from a `.transcript.json`, SRT or WebVTT file,
+/// chosen by the file extension.
+pub fn read_segments(path: &Path) -> Result<Vec<TranscriptSegment>> {
+    let format = InputFormat::from_path(path)?;
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+
+    let segments = match format {
+        InputFormat::TranscriptJson => serde_json::from_str(&content).map_err(Into::into),
+        InputFormat::Srt => parse_srt(&content),
+        InputFormat::Vtt => parse_vtt(&content),
+    }
+    .with_context(|| format!("Failed to parse {:?}", path))?;
+
+    Ok(segments)
+}
+
+pub fn parse_srt(content: &str) -> Result<Vec<TranscriptSegment>> {
+    let mut segments = Vec::new();
+
+    for (line_no, block) in blocks(content) {
+        let mut lines = block.iter().enumerate();
+        let (mut offset, mut line) = lines.next().unwrap();
+
+        // The cue number is optional in practice
+        if !line.contains("-->") {
+            if line.trim().parse::<u64>().is_err() {
+                bail!("line {}: expected cue number, got {:?}", line_no, line);
+            }
+            (offset, line) = lines
+                .next()
+                .ok_or_else(|| anyhow!("line {}: cue has no timestamp line", line_no + 1))?;
+        }
+
+        let (start, end) =
+            parse_timing(line).map_err(|e| anyhow!("line {}: {}", line_no + offset, e))?;
+        let text = clean_text(lines.map(|(_, l)| *l));
+        segments.push(TranscriptSegment {
+            start,
+            end,
+            text,
+            ..Default::default()
+        });
+    }
+
+    Ok(segments)
+}
+
+pub fn parse_vtt(content: &str) -> Result<Vec<TranscriptSegment>> {
+    let mut segments = Vec::new();
+    let mut blocks = blocks(content);
+
+    match blocks.next() {
+        Some((_, header)) if header[0].starts_with("WEBVTT") => {}
+        _ => bail!("line 1: missing WEBVTT header"),
+    }
+
+    for (line_no, block) in blocks {
+        let first = block[0];
+        if first.starts_with("NOTE") || first.starts_with("STYLE") || first.starts_with("REGION") {
+            continue;
+        }
+
+        // Optional cue identifier before the timing line
+        let offset = if first.contains("-->") { 0 } else { 1 };
+        let line = block
+            .get(offset)
+            .ok_or_else(|| anyhow!("line {}: cue has no timestamp line", line_no + 1))?;
+
+        let (start, end) =
+            parse_timing(line).map_err(|e| anyhow!("line {}: {}", line_no + offset, e))?;
+        let text = clean_text(block[offset + 1..].iter().copied());
+        segments.push(TranscriptSegment {
+            start,
+            end,
+            text,
+            ..Default::default()
+        });
+    }
+
+    Ok(segments)
+}
+
+/// Splits content into blank-line separated blocks, with the 1-based line
+/// number of each block's first line.
+fn blocks(content: &str) -> impl Iterator<Item = (usize, Vec<&str>)> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let mut blocks = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut start = 0;
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push((start, std::mem::take(&mut current)));
+            }
+        } else {
+            if current.is_empty() {
+                start = i + 1;
+            }
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        blocks.push((start, current));
+    }
+
+    blocks.into_iter()
+}
+
+/// Parses `start --> end [settings]` into centiseconds.
+fn parse_timing(line: &str) -> Result<(i64, i64)> {
+    let (start, rest) = line
+        .split_once("-->")
+        .ok_or_else(|| anyhow!("expected timestamp line, got {:?}", line))?;
+    let end = rest.split_whitespace().next().unwrap_or_default();
+
+    let start = parse_timestamp(start.trim())?;
+    let end = parse_timestamp(end)?;
+    if end < start {
+        bail!("cue ends before it starts: {:?}", line);
+    }
+
+    Ok((start, end))
+}
+
+/// Parses `[hh:]mm:ss,mmm` into centiseconds. Both `,` and `.` are accepted
+/// before the milliseconds in SRT and WebVTT alike, since real-world files
+/// mix them up.
+fn parse_timestamp(s: &str) -> Result<i64> {
+    let invalid = || anyhow!("invalid timestamp {:?} (expected hh:mm:ss,mmm)", s);
+
+    let (clock, millis) = s.split_once([',', '.']).ok_or_else(invalid)?;
+    let parts: Vec<&str> = clock.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [h, m, s] => (*h, *m, *s),
+        [m, s] => ("0", *m, *s),
+        _ => return Err(invalid()),
+    };
+
+    // Digits only, so no sign
+    let parse = |v: &str| {
+        if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        v.parse::<i64>().map_err(|_| invalid())
+    };
+    let (hours, minutes, seconds) = (parse(hours)?, parse(minutes)?, parse(seconds)?);
+    if minutes >= 60 || seconds >= 60 || millis.is_empty() || millis.len() > 3 {
+        return Err(invalid());
+    }
+    // "5" means 500ms, as in "00:00:01,5"
+    let millis = parse(&format!("{:0<3}", millis))?;
+
+    let ms = ((hours * 60 + minutes) * 60 + seconds) * 1000 + millis;
+    Ok((ms + 5) / 10)
+}
+
+/// Joins cue lines and strips formatting tags (`<i>`, `<v Speaker>`, `{\an8}`).
+fn clean_text<'a>(lines: impl Iterator<Item = &'a str>) -> String {
+    let joined = lines.map(str::trim).collect::<Vec<_>>().join(" ");
+    TAG_RE
+        .replace_all(&joined, "")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_srt() {
+        let content = "\u{feff}1\r\n00:00:01,500 --> 00:00:03,204\r\n<i>Hello</i>\r\nworld\r\n\r\n\
+                       2\r\n01:00:00,000 --> 01:00:02,000\r\n{\\an8}Bye\r\n";
+        let segments = parse_srt(content).unwrap();
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[0].start, segments[0].end), (150, 320));
+        assert_eq!(segments[0].text, "Hello world");
+        assert_eq!((segments[1].start, segments[1].end), (360000, 360200));
+        assert_eq!(segments[1].text, "Bye");
+    }
+
+    #[test]
+    fn test_parse_vtt() {
+        let content = "WEBVTT - title\n\nNOTE generated\n\nintro\n00:01.000 --> 00:02.500 line:85%\n\
+                       <v Alice>Tom &amp; Jerry</v>\n\n00:00:03.000 --> 00:00:04.000\nNext\n";
+        let segments = parse_vtt(content).unwrap();
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[0].start, segments[0].end), (100, 250));
+        assert_eq!(segments[0].text, "Tom & Jerry");
+        assert_eq!(segments[1].start, 300);
+    }
+
+    #[test]
+    fn test_malformed_cue() {
+        let err = parse_srt("1\n00:00:01,000 --> 00:00:xx,000\nHello\n").unwrap_err();
+        assert!(err.to_string().starts_with("line 2: invalid timestamp"));
+        assert!(parse_srt("1\n-01:00:01,000 --> 00:00:02,000\nHello\n").is_err());
+        assert!(parse_srt("1\n00:00:01,000 --> +00:00:02,000\nHello\n").is_err());
+        // A `.` in SRT is a common slip, and still accepted
+        let segments = parse_srt("1\n00:00:01.000 --> 00:00:02,000\nHello\n").unwrap();
+        assert_eq!((segments[0].start, segments[0].end), (100, 200));
+
+        let err = parse_vtt("1\n00:00:01.000 --> 00:00:02.000\nHello\n").unwrap_err();
+        assert!(err.to_string().contains("missing WEBVTT header"));
+    }
+}
@@ -1,6 +1,9 @@
+pub mod checkpoint;
 pub mod config;
 pub mod ffmpeg_decoder;
+pub mod input;
 pub mod llm;
 pub mod output;
+pub mod subtitle;
 pub mod transcribe;
 pub mod translate;
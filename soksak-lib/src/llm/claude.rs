@@ -0,0 +1,157 @@
+use crate::config::LlmProviderConfig;
+use crate::llm::{
+    BoxFuture, Capabilities, JsonFormat, LlmProvider, Message, Transport, split_system,
+};
+use anyhow::{Context, Result};
+use serde_json::json;
+
+/// Anthropic's Messages API.
+pub struct ClaudeProvider {
+    config: LlmProviderConfig,
+}
+
+impl ClaudeProvider {
+    pub fn new(config: LlmProviderConfig) -> Self {
+        Self { config }
+    }
+
+    fn url(&self) -> String {
+        if let Some(base_url) = &self.config.base_url {
+            format!("{}/v1/messages", base_url.trim_end_matches('/'))
+        } else {
+            "https://api.anthropic.com/v1/messages".to_string()
+        }
+    }
+
+    async fn complete(
+        &self,
+        transport: &Transport,
+        model: &str,
+        messages: &[Message],
+        format: Option<JsonFormat<'_>>,
+    ) -> Result<String> {
+        let body = body(model, messages, format);
+
+        let request = transport
+            .client()
+            .post(self.url())
+            .header(
+                "x-api-key",
+                self.config.api_key.as_deref().unwrap_or_default(),
+            )
+            .header("anthropic-version", "2023-06-01")
+            .json(&body);
+
+        let response = transport.send(request, messages).await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            anyhow::bail!("Claude API error: {}", error_text);
+        }
+
+        let response_json: serde_json::Value = response.json().await?;
+        let blocks = response_json["content"]
+            .as_array()
+            .context("Failed to parse Claude response content")?;
+
+        // Structured output comes back as the arguments of the forced tool call
+        let content = match format {
+            None => blocks
+                .iter()
+                .find(|block| block["type"] == "text")
+                .and_then(|block| block["text"].as_str())
+                .context("Failed to parse Claude response content")?
+                .to_string(),
+            Some(_) => blocks
+                .iter()
+                .find(|block| block["type"] == "tool_use")
+                .map(|block| block["input"].to_string())
+                .context("Claude response has no tool_use block")?,
+        };
+
+        Ok(content)
+    }
+}
+
+/// Messages API request; the system prompt goes in the top-level `system`
+/// field, the only place it is accepted. Claude has no JSON mode, so JSON is
+/// asked for by forcing a call to a `response` tool whose input schema is the
+/// response schema.
+fn body(model: &str, messages: &[Message], format: Option<JsonFormat>) -> serde_json::Value {
+    let (system, messages) = split_system(messages);
+    let mut body = json!({
+        "model": model,
+        "messages": messages,
+        "max_tokens": 4096, // Reasonable default
+    });
+    let body_map = body.as_object_mut().unwrap();
+    if let Some(system) = system {
+        body_map.insert("system".to_string(), json!(system));
+    }
+
+    let input_schema = match format {
+        None => return body,
+        Some(JsonFormat::Object) => json!({ "type": "object" }),
+        Some(JsonFormat::Schema(schema)) => schema.clone(),
+    };
+    body_map.insert(
+        "tools".to_string(),
+        json!([{
+            "name": "response",
+            "description": "Respond with the requested JSON object.",
+            "input_schema": input_schema
+        }]),
+    );
+    body_map.insert(
+        "tool_choice".to_string(),
+        json!({ "type": "tool", "name": "response" }),
+    );
+    body
+}
+
+impl LlmProvider for ClaudeProvider {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            json_object: true,
+            json_schema: true,
+        }
+    }
+
+    fn chat<'a>(
+        &'a self,
+        transport: &'a Transport,
+        model: &'a str,
+        messages: &'a [Message],
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(self.complete(transport, model, messages, None))
+    }
+
+    fn structured_chat<'a>(
+        &'a self,
+        transport: &'a Transport,
+        model: &'a str,
+        messages: &'a [Message],
+        format: JsonFormat<'a>,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(self.complete(transport, model, messages, Some(format)))
+    }
+
+    fn curl_command(
+        &self,
+        model: &str,
+        messages: &[Message],
+        format: Option<JsonFormat>,
+    ) -> String {
+        let body = body(model, messages, format);
+
+        let mut cmd = format!("curl -X POST \"{}\"", self.url());
+        cmd.push_str(" \\\n  -H \"Content-Type: application/json\"");
+        cmd.push_str(&format!(
+            " \\\n  -H \"x-api-key: {}\"",
+            self.config.api_key.as_deref().unwrap_or_default()
+        ));
+        cmd.push_str(" \\\n  -H \"anthropic-version: 2023-06-01\"");
+        cmd.push_str(&format!(" \\\n  -d '{}'", body));
+        cmd
+    }
+}
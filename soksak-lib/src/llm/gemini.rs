@@ -0,0 +1,174 @@
+use crate::config::LlmProviderConfig;
+use crate::llm::{
+    BoxFuture, Capabilities, JsonFormat, LlmProvider, Message, Transport, split_system,
+};
+use anyhow::{Context, Result};
+use serde_json::json;
+
+/// Google's generateContent API.
+pub struct GeminiProvider {
+    config: LlmProviderConfig,
+}
+
+impl GeminiProvider {
+    pub fn new(config: LlmProviderConfig) -> Self {
+        Self { config }
+    }
+
+    fn url(&self, model: &str, api_key: &str) -> String {
+        let base_url = self
+            .config
+            .base_url
+            .as_deref()
+            .unwrap_or("https://generativelanguage.googleapis.com");
+        format!(
+            "{}/v1beta/models/{}:generateContent?key={}",
+            base_url.trim_end_matches('/'),
+            model,
+            api_key
+        )
+    }
+
+    async fn complete(
+        &self,
+        transport: &Transport,
+        model: &str,
+        messages: &[Message],
+        format: Option<JsonFormat<'_>>,
+    ) -> Result<String> {
+        let api_key = self
+            .config
+            .api_key
+            .as_deref()
+            .context("API key is required for Gemini")?;
+        let body = body(messages, format);
+
+        let request = transport
+            .client()
+            .post(self.url(model, api_key))
+            .json(&body);
+        let response = transport.send(request, messages).await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            anyhow::bail!("Gemini API error: {}", error_text);
+        }
+
+        let response_json: serde_json::Value = response.json().await?;
+
+        let content = response_json["candidates"][0]["content"]["parts"][0]["text"]
+            .as_str()
+            .context("Failed to parse Gemini response content")?
+            .to_string();
+
+        Ok(content)
+    }
+}
+
+/// generateContent request; the system prompt goes in `systemInstruction`
+/// and assistant turns take the `model` role.
+fn body(messages: &[Message], format: Option<JsonFormat>) -> serde_json::Value {
+    let (system, messages) = split_system(messages);
+    let contents: Vec<serde_json::Value> = messages
+        .iter()
+        .map(|msg| {
+            let role = if msg.role == "assistant" {
+                "model"
+            } else {
+                "user"
+            };
+            json!({
+                "role": role,
+                "parts": [{ "text": msg.content }]
+            })
+        })
+        .collect();
+
+    let mut body = json!({
+        "contents": contents,
+    });
+    let body_map = body.as_object_mut().unwrap();
+    if let Some(system) = system {
+        body_map.insert(
+            "systemInstruction".to_string(),
+            json!({ "parts": [{ "text": system }] }),
+        );
+    }
+
+    match format {
+        None => {}
+        Some(JsonFormat::Object) => {
+            body_map.insert(
+                "generationConfig".to_string(),
+                json!({ "responseMimeType": "application/json" }),
+            );
+        }
+        Some(JsonFormat::Schema(schema)) => {
+            body_map.insert(
+                "generationConfig".to_string(),
+                json!({
+                    "responseMimeType": "application/json",
+                    "responseSchema": response_schema(schema)
+                }),
+            );
+        }
+    }
+    body
+}
+
+/// `responseSchema` takes an OpenAPI subset of JSON Schema without
+/// `additionalProperties`, which the strict OpenAI schemas always set.
+fn response_schema(schema: &serde_json::Value) -> serde_json::Value {
+    match schema {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "additionalProperties" | "$schema"))
+            .map(|(key, value)| (key.clone(), response_schema(value)))
+            .collect(),
+        serde_json::Value::Array(items) => items.iter().map(response_schema).collect(),
+        other => other.clone(),
+    }
+}
+
+impl LlmProvider for GeminiProvider {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            json_object: true,
+            json_schema: true,
+        }
+    }
+
+    fn chat<'a>(
+        &'a self,
+        transport: &'a Transport,
+        model: &'a str,
+        messages: &'a [Message],
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(self.complete(transport, model, messages, None))
+    }
+
+    fn structured_chat<'a>(
+        &'a self,
+        transport: &'a Transport,
+        model: &'a str,
+        messages: &'a [Message],
+        format: JsonFormat<'a>,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(self.complete(transport, model, messages, Some(format)))
+    }
+
+    fn curl_command(
+        &self,
+        model: &str,
+        messages: &[Message],
+        format: Option<JsonFormat>,
+    ) -> String {
+        let api_key = self.config.api_key.as_deref().unwrap_or("YOUR_API_KEY");
+        let body = body(messages, format);
+
+        let mut cmd = format!("curl -X POST \"{}\"", self.url(model, api_key));
+        cmd.push_str(" \\\n  -H \"Content-Type: application/json\"");
+        cmd.push_str(&format!(" \\\n  -d '{}'", body));
+        cmd
+    }
+}
@@ -0,0 +1,671 @@
+mod claude;
+mod gemini;
+mod openai;
+mod retry;
+
+pub use claude::ClaudeProvider;
+pub use gemini::GeminiProvider;
+pub use openai::OpenAiProvider;
+pub use retry::Transport;
+
+use crate::config::{ApiType, JsonModeType, LlmProviderConfig};
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+const DEFAULT_MAX_INPUT_TOKENS: u32 = 100_000;
+
+/// Roughly four bytes per token.
+pub fn estimate_tokens(messages: &[Message]) -> u64 {
+    messages.iter().map(|m| m.content.len() as u64).sum::<u64>() / 4
+}
+
+/// Structured output an API can enforce.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+    pub json_object: bool, // any JSON object
+    pub json_schema: bool, // JSON matching a given schema
+}
+
+/// JSON output asked of `LlmProvider::structured_chat`.
+#[derive(Debug, Clone, Copy)]
+pub enum JsonFormat<'a> {
+    Object,
+    Schema(&'a serde_json::Value),
+}
+
+/// One LLM API. Built-in providers cover the `ApiType`s; others can be added
+/// with `register_provider` and selected by their name as `api_type`.
+pub trait LlmProvider: Send + Sync {
+    /// Which `JsonFormat`s `structured_chat` enforces; requests for others
+    /// fall back to the next weaker format, and then to plain `chat`.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Sends the conversation and returns the reply.
+    fn chat<'a>(
+        &'a self,
+        transport: &'a Transport,
+        model: &'a str,
+        messages: &'a [Message],
+    ) -> BoxFuture<'a, Result<String>>;
+
+    /// Like `chat`, with the reply in `format`, returned as JSON text.
+    fn structured_chat<'a>(
+        &'a self,
+        transport: &'a Transport,
+        model: &'a str,
+        messages: &'a [Message],
+        _format: JsonFormat<'a>,
+    ) -> BoxFuture<'a, Result<String>> {
+        self.chat(transport, model, messages)
+    }
+
+    /// The request `chat` or `structured_chat` would send, as a curl command
+    /// for debugging.
+    fn curl_command(&self, model: &str, messages: &[Message], format: Option<JsonFormat>)
+    -> String;
+}
+
+type ProviderFactory = dyn Fn(&LlmProviderConfig) -> Result<Arc<dyn LlmProvider>> + Send + Sync;
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<ProviderFactory>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Arc<ProviderFactory>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Makes `factory` build the provider for every `llm.providers` entry whose
+/// `api_type` is `name`, replacing any earlier registration of the name.
+/// Built-in `ApiType` names can't be overridden.
+pub fn register_provider<F>(name: &str, factory: F)
+where
+    F: Fn(&LlmProviderConfig) -> Result<Arc<dyn LlmProvider>> + Send + Sync + 'static,
+{
+    registry()
+        .write()
+        .unwrap()
+        .insert(name.to_string(), Arc::new(factory));
+}
+
+fn create_provider(config: &LlmProviderConfig) -> Result<Arc<dyn LlmProvider>> {
+    Ok(match &config.api_type {
+        ApiType::OpenAI | ApiType::Ollama => Arc::new(OpenAiProvider::new(config.clone())),
+        ApiType::Claude => Arc::new(ClaudeProvider::new(config.clone())),
+        ApiType::Gemini => Arc::new(GeminiProvider::new(config.clone())),
+        ApiType::Custom(name) => {
+            let factory = registry().read().unwrap().get(name).cloned();
+            match factory {
+                Some(factory) => factory(config)?,
+                None => anyhow::bail!(
+                    "No LLM provider registered for api_type {:?} (provider {})",
+                    name,
+                    config.id
+                ),
+            }
+        }
+    })
+}
+
+#[derive(Clone)]
+pub struct LlmClient {
+    provider: Arc<dyn LlmProvider>,
+    config: LlmProviderConfig,
+    transport: Transport,
+}
+
+impl LlmClient {
+    /// A client for the provider `config.api_type` names, built in or
+    /// registered with `register_provider`.
+    pub fn new(config: LlmProviderConfig) -> Result<Self> {
+        let provider = create_provider(&config)?;
+        Ok(Self::with_provider(config, provider))
+    }
+
+    /// A client sending requests through `provider`, with the timeout,
+    /// retries and rate limits of `config`.
+    pub fn with_provider(config: LlmProviderConfig, provider: Arc<dyn LlmProvider>) -> Self {
+        Self {
+            transport: Transport::new(&config),
+            provider,
+            config,
+        }
+    }
+
+    /// How the provider is asked for JSON output.
+    pub fn json_mode_type(&self) -> &JsonModeType {
+        &self.config.json_mode_type
+    }
+
+    /// No particular format unless JSON is asked for and the provider
+    /// allows it; a schema is only enforced with `JsonModeType::JsonSchema`
+    /// and by providers that support it.
+    fn json_format<'a>(
+        &self,
+        json_mode: bool,
+        response_schema: Option<&'a serde_json::Value>,
+    ) -> Option<JsonFormat<'a>> {
+        if !json_mode {
+            return None;
+        }
+        let format = match (&self.config.json_mode_type, response_schema) {
+            (JsonModeType::None, _) => return None,
+            (JsonModeType::JsonObject, _) => JsonFormat::Object,
+            (JsonModeType::JsonSchema, Some(schema)) => JsonFormat::Schema(schema),
+            (JsonModeType::JsonSchema, None) => {
+                log::warn!("JsonSchema mode enabled but no schema provided, falling back to JSON");
+                JsonFormat::Object
+            }
+        };
+
+        let capabilities = self.provider.capabilities();
+        match format {
+            JsonFormat::Schema(_) if capabilities.json_schema => Some(format),
+            _ if capabilities.json_object => Some(JsonFormat::Object),
+            _ => None,
+        }
+    }
+
+    /// Fails if `messages` are estimated to be longer than the provider's
+    /// `max_input_tokens`, instead of sending a request it would reject.
+    pub fn check_input(&self, messages: &[Message]) -> Result<()> {
+        let tokens = estimate_tokens(messages);
+        let limit = self
+            .config
+            .max_input_tokens
+            .unwrap_or(DEFAULT_MAX_INPUT_TOKENS);
+        if tokens > limit as u64 {
+            bail!(
+                "Request to {} is about {} tokens, more than its max_input_tokens of {}",
+                self.config.id,
+                tokens,
+                limit
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn chat_completion(
+        &self,
+        model: &str,
+        messages: Vec<Message>,
+        json_mode: bool,
+        response_schema: Option<serde_json::Value>,
+    ) -> Result<String> {
+        self.check_input(&messages)?;
+        match self.json_format(json_mode, response_schema.as_ref()) {
+            Some(format) => {
+                self.provider
+                    .structured_chat(&self.transport, model, &messages, format)
+                    .await
+            }
+            None => self.provider.chat(&self.transport, model, &messages).await,
+        }
+    }
+
+    pub fn get_curl_command(
+        &self,
+        model: &str,
+        messages: &[Message],
+        json_mode: bool,
+        response_schema: Option<&serde_json::Value>,
+    ) -> String {
+        let format = self.json_format(json_mode, response_schema);
+        self.provider.curl_command(model, messages, format)
+    }
+}
+
+/// Joins the system messages into one prompt, for APIs that take it apart
+/// from the conversation, and returns the remaining messages.
+pub fn split_system(messages: &[Message]) -> (Option<String>, Vec<&Message>) {
+    let (system, rest): (Vec<&Message>, Vec<&Message>) =
+        messages.iter().partition(|msg| msg.role == "system");
+    let system = (!system.is_empty()).then(|| {
+        system
+            .iter()
+            .map(|msg| msg.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    });
+    (system, rest)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Reads one HTTP request, returning its path and JSON body.
+    async fn read_request(stream: &mut TcpStream) -> (String, serde_json::Value) {
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        let (head_len, content_length) = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8_lossy(&request[..pos]).to_lowercase();
+            let content_length = head
+                .lines()
+                .find_map(|l| l.strip_prefix("content-length:"))
+                .map(|v| v.trim().parse::<usize>().unwrap())
+                .unwrap_or(0);
+            break (pos + 4, content_length);
+        };
+        while request.len() < head_len + content_length {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let head = String::from_utf8_lossy(&request[..head_len]).to_string();
+        let path = head.split_whitespace().nth(1).unwrap().to_string();
+        let body = serde_json::from_slice(&request[head_len..]).unwrap();
+        (path, body)
+    }
+
+    /// Answers requests on localhost with `responses` in turn, each a status,
+    /// extra header lines and a JSON body, and hands back every request's
+    /// path and JSON body.
+    async fn stub_server(
+        responses: Vec<(u16, &'static str, serde_json::Value)>,
+    ) -> (
+        String,
+        tokio::task::JoinHandle<Vec<(String, serde_json::Value)>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for (status, headers, body) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                requests.push(read_request(&mut stream).await);
+
+                let body = body.to_string();
+                let reply = format!(
+                    "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    headers,
+                    body.len(),
+                    body
+                );
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (base_url, handle)
+    }
+
+    /// Answers a single request with `response`.
+    async fn mock_server(
+        response: serde_json::Value,
+    ) -> (
+        String,
+        tokio::task::JoinHandle<Vec<(String, serde_json::Value)>>,
+    ) {
+        stub_server(vec![(200, "", response)]).await
+    }
+
+    fn provider(api_type: ApiType, base_url: String) -> LlmProviderConfig {
+        LlmProviderConfig {
+            id: "test".to_string(),
+            base_url: Some(base_url),
+            api_key: Some("test-key".to_string()),
+            api_type,
+            json_mode_type: JsonModeType::JsonSchema,
+            timeout: None,
+            max_retries: None,
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_input_tokens: None,
+        }
+    }
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message {
+                role: "system".to_string(),
+                content: "Translate into Korean.".to_string(),
+            },
+            Message {
+                role: "user".to_string(),
+                content: "Hello".to_string(),
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_claude_system_prompt() {
+        let (base_url, server) =
+            mock_server(json!({ "content": [{ "type": "text", "text": "안녕하세요" }] })).await;
+        let client = LlmClient::new(provider(ApiType::Claude, base_url)).unwrap();
+
+        let content = client
+            .chat_completion("claude-sonnet", conversation(), false, None)
+            .await
+            .unwrap();
+        assert_eq!(content, "안녕하세요");
+
+        let (path, body) = server.await.unwrap().remove(0);
+        assert_eq!(path, "/v1/messages");
+        assert_eq!(body["system"], "Translate into Korean.");
+        assert_eq!(
+            body["messages"],
+            json!([{ "role": "user", "content": "Hello" }])
+        );
+    }
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": { "text": { "type": "string" } },
+            "required": ["text"],
+            "additionalProperties": false
+        })
+    }
+
+    #[tokio::test]
+    async fn test_claude_tool_use_schema() {
+        let (base_url, server) = mock_server(json!({
+            "content": [{
+                "type": "tool_use",
+                "id": "toolu_1",
+                "name": "response",
+                "input": { "text": "안녕하세요" }
+            }]
+        }))
+        .await;
+        let client = LlmClient::new(provider(ApiType::Claude, base_url)).unwrap();
+
+        let content = client
+            .chat_completion("claude-sonnet", conversation(), true, Some(schema()))
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&content).unwrap(),
+            json!({ "text": "안녕하세요" })
+        );
+
+        let (_, body) = server.await.unwrap().remove(0);
+        assert_eq!(body["tools"][0]["input_schema"], schema());
+        assert_eq!(
+            body["tool_choice"],
+            json!({ "type": "tool", "name": "response" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gemini_response_schema() {
+        let (base_url, server) = mock_server(json!({
+            "candidates": [{ "content": { "parts": [{ "text": "{\"text\": \"안녕하세요\"}" }] } }]
+        }))
+        .await;
+        let client = LlmClient::new(provider(ApiType::Gemini, base_url)).unwrap();
+
+        client
+            .chat_completion("gemini-pro", conversation(), true, Some(schema()))
+            .await
+            .unwrap();
+
+        let (_, body) = server.await.unwrap().remove(0);
+        assert_eq!(
+            body["generationConfig"],
+            json!({
+                "responseMimeType": "application/json",
+                "responseSchema": {
+                    "type": "object",
+                    "properties": { "text": { "type": "string" } },
+                    "required": ["text"]
+                }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gemini_system_instruction() {
+        let (base_url, server) = mock_server(json!({
+            "candidates": [{ "content": { "parts": [{ "text": "안녕하세요" }] } }]
+        }))
+        .await;
+        let client = LlmClient::new(provider(ApiType::Gemini, base_url)).unwrap();
+
+        let content = client
+            .chat_completion("gemini-pro", conversation(), false, None)
+            .await
+            .unwrap();
+        assert_eq!(content, "안녕하세요");
+
+        let (path, body) = server.await.unwrap().remove(0);
+        assert_eq!(
+            path,
+            "/v1beta/models/gemini-pro:generateContent?key=test-key"
+        );
+        assert_eq!(
+            body["systemInstruction"],
+            json!({ "parts": [{ "text": "Translate into Korean." }] })
+        );
+        assert_eq!(
+            body["contents"],
+            json!([{ "role": "user", "parts": [{ "text": "Hello" }] }])
+        );
+    }
+
+    fn openai_reply(content: &str) -> serde_json::Value {
+        json!({ "choices": [{ "message": { "role": "assistant", "content": content } }] })
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_errors() {
+        let (base_url, server) = stub_server(vec![
+            (
+                429,
+                "Retry-After: 0\r\n",
+                json!({ "error": "rate limited" }),
+            ),
+            (
+                503,
+                "retry-after-ms: 10\r\n",
+                json!({ "error": "overloaded" }),
+            ),
+            (200, "", openai_reply("안녕하세요")),
+        ])
+        .await;
+        let client = LlmClient::new(provider(ApiType::OpenAI, base_url)).unwrap();
+
+        let content = client
+            .chat_completion("gpt-4o", conversation(), false, None)
+            .await
+            .unwrap();
+        assert_eq!(content, "안녕하세요");
+        assert_eq!(server.await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        let (base_url, server) = stub_server(vec![
+            (500, "Retry-After: 0\r\n", json!({ "error": "first" })),
+            (500, "Retry-After: 0\r\n", json!({ "error": "second" })),
+        ])
+        .await;
+        let client = LlmClient::new(LlmProviderConfig {
+            max_retries: Some(1),
+            ..provider(ApiType::OpenAI, base_url)
+        })
+        .unwrap();
+        let error = client
+            .chat_completion("gpt-4o", conversation(), false, None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("second"));
+        assert_eq!(server.await.unwrap().len(), 2);
+
+        // Client errors are not retried
+        let (base_url, server) =
+            stub_server(vec![(400, "", json!({ "error": "bad request" }))]).await;
+        let client = LlmClient::new(provider(ApiType::OpenAI, base_url)).unwrap();
+        assert!(
+            client
+                .chat_completion("gpt-4o", conversation(), false, None)
+                .await
+                .is_err()
+        );
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_too_long() {
+        // Nothing listens here, so any request sent would fail differently
+        let client = LlmClient::new(LlmProviderConfig {
+            max_input_tokens: Some(5),
+            ..provider(ApiType::OpenAI, "http://127.0.0.1:9".to_string())
+        })
+        .unwrap();
+        let error = client
+            .chat_completion("gpt-4o", conversation(), false, None)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("max_input_tokens of 5"));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        // Accept connections but never answer
+        let server = tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+
+        let client = LlmClient::new(LlmProviderConfig {
+            timeout: Some(0.2),
+            max_retries: Some(0),
+            ..provider(ApiType::OpenAI, base_url)
+        })
+        .unwrap();
+        let started = std::time::Instant::now();
+        assert!(
+            client
+                .chat_completion("gpt-4o", conversation(), false, None)
+                .await
+                .is_err()
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+        server.abort();
+    }
+
+    /// Answers with the last message, without any JSON support.
+    struct EchoProvider;
+
+    impl LlmProvider for EchoProvider {
+        fn chat<'a>(
+            &'a self,
+            _transport: &'a Transport,
+            model: &'a str,
+            messages: &'a [Message],
+        ) -> BoxFuture<'a, Result<String>> {
+            Box::pin(async move {
+                let last = messages.last().map(|m| m.content.as_str());
+                Ok(format!("{}: {}", model, last.unwrap_or_default()))
+            })
+        }
+
+        fn curl_command(
+            &self,
+            model: &str,
+            _messages: &[Message],
+            format: Option<JsonFormat>,
+        ) -> String {
+            format!("echo {} {:?}", model, format)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registered_provider() {
+        register_provider("Echo", |_| Ok(Arc::new(EchoProvider)));
+        let config: LlmProviderConfig =
+            serde_yaml::from_str("id: gateway\napi_type: Echo\njson_mode_type: JsonSchema")
+                .unwrap();
+        assert!(matches!(&config.api_type, ApiType::Custom(name) if name == "Echo"));
+        assert!(matches!(
+            serde_yaml::from_str::<ApiType>("Claude").unwrap(),
+            ApiType::Claude
+        ));
+
+        // Without JSON support, structured requests go out as plain chat
+        let client = LlmClient::new(config).unwrap();
+        let content = client
+            .chat_completion("internal", conversation(), true, Some(schema()))
+            .await
+            .unwrap();
+        assert_eq!(content, "internal: Hello");
+        assert_eq!(
+            client.get_curl_command("internal", &conversation(), true, Some(&schema())),
+            "echo internal None"
+        );
+
+        let unknown: LlmProviderConfig =
+            serde_yaml::from_str("id: other\napi_type: Unknown").unwrap();
+        assert!(LlmClient::new(unknown).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore] // Ignored by default, run explicitly to test local LLM
+    async fn test_lmstudio_json_mode() {
+        let provider = LlmProviderConfig {
+            id: "lmstudio".to_string(),
+            base_url: Some("http://localhost:1234".to_string()),
+            api_key: Some("lm-studio".to_string()),
+            api_type: ApiType::OpenAI,
+            json_mode_type: JsonModeType::None, // Explicitly disable JSON mode to fix the error
+            timeout: None,
+            max_retries: None,
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_input_tokens: None,
+        };
+
+        let client = LlmClient::new(provider).unwrap();
+        let messages = vec![
+            Message {
+                role: "system".to_string(),
+                content: "You are a helpful assistant. Output JSON.".to_string(),
+            },
+            Message {
+                role: "user".to_string(),
+                content: "Say hello in JSON format: {\"message\": \"hello\"}".to_string(),
+            },
+        ];
+
+        // This should succeed now (or fail with model error, but NOT response_format error)
+        let result = client
+            .chat_completion("gpt-oss-120b", messages, true, None)
+            .await;
+
+        match result {
+            Ok(res) => println!("Success: {}", res),
+            Err(e) => {
+                println!("Error: {}", e);
+                if e.to_string().contains("response_format.type") {
+                    panic!("Failed: Still getting response_format error");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::config::{ApiType, LlmProviderConfig};
+use crate::llm::{BoxFuture, Capabilities, JsonFormat, LlmProvider, Message, Transport};
+use anyhow::{Context, Result};
+use serde_json::json;
+
+/// The Chat Completions API, also served by Ollama and most local servers.
+pub struct OpenAiProvider {
+    config: LlmProviderConfig,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: LlmProviderConfig) -> Self {
+        Self { config }
+    }
+
+    fn is_ollama(&self) -> bool {
+        matches!(self.config.api_type, ApiType::Ollama)
+    }
+
+    fn url(&self) -> String {
+        let default_url = if self.is_ollama() {
+            "http://localhost:11434/v1/chat/completions"
+        } else {
+            "https://api.openai.com/v1/chat/completions"
+        };
+
+        if let Some(base_url) = &self.config.base_url {
+            format!("{}/v1/chat/completions", base_url.trim_end_matches('/'))
+        } else {
+            default_url.to_string()
+        }
+    }
+
+    fn body(
+        &self,
+        model: &str,
+        messages: &[Message],
+        format: Option<JsonFormat>,
+    ) -> serde_json::Value {
+        let mut body = json!({
+            "model": model,
+            "messages": messages,
+        });
+
+        match format {
+            None => {}
+            Some(JsonFormat::Object) => {
+                if self.is_ollama() {
+                    body.as_object_mut()
+                        .unwrap()
+                        .insert("format".to_string(), json!("json"));
+                } else {
+                    body.as_object_mut().unwrap().insert(
+                        "response_format".to_string(),
+                        json!({ "type": "json_object" }),
+                    );
+                }
+            }
+            Some(JsonFormat::Schema(schema)) => {
+                body.as_object_mut().unwrap().insert(
+                    "response_format".to_string(),
+                    json!({
+                        "type": "json_schema",
+                        "json_schema": {
+                            "name": "response",
+                            "strict": true,
+                            "schema": schema
+                        }
+                    }),
+                );
+            }
+        }
+        body
+    }
+
+    async fn complete(
+        &self,
+        transport: &Transport,
+        model: &str,
+        messages: &[Message],
+        format: Option<JsonFormat<'_>>,
+    ) -> Result<String> {
+        let url = self.url();
+        let mut current_body = self.body(model, messages, format);
+        let mut retry_count = 0;
+
+        loop {
+            let mut request = transport.client().post(&url).json(&current_body);
+
+            if let Some(api_key) = &self.config.api_key {
+                request = request.header("Authorization", format!("Bearer {}", api_key));
+            }
+
+            let response = transport.send(request, messages).await?;
+
+            if response.status().is_success() {
+                let response_json: serde_json::Value = response.json().await?;
+                let content = response_json["choices"][0]["message"]["content"]
+                    .as_str()
+                    .context("Failed to parse LLM response content")?
+                    .to_string();
+                return Ok(content);
+            } else {
+                let error_text = response.text().await?;
+                // Check if the error is about response_format not supporting json_object
+                // Error example: "'response_format.type' must be 'json_schema' or 'text'"
+                if retry_count == 0
+                    && error_text.contains("response_format")
+                    && error_text.contains("json_schema")
+                {
+                    eprintln!(
+                        "Warning: Provider does not support 'json_object'. Retrying without response_format..."
+                    );
+                    if let Some(obj) = current_body.as_object_mut() {
+                        obj.remove("response_format");
+                    }
+                    retry_count += 1;
+                    continue;
+                }
+                anyhow::bail!("LLM API error: {}", error_text);
+            }
+        }
+    }
+}
+
+impl LlmProvider for OpenAiProvider {
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            json_object: true,
+            json_schema: true,
+        }
+    }
+
+    fn chat<'a>(
+        &'a self,
+        transport: &'a Transport,
+        model: &'a str,
+        messages: &'a [Message],
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(self.complete(transport, model, messages, None))
+    }
+
+    fn structured_chat<'a>(
+        &'a self,
+        transport: &'a Transport,
+        model: &'a str,
+        messages: &'a [Message],
+        format: JsonFormat<'a>,
+    ) -> BoxFuture<'a, Result<String>> {
+        Box::pin(self.complete(transport, model, messages, Some(format)))
+    }
+
+    fn curl_command(
+        &self,
+        model: &str,
+        messages: &[Message],
+        format: Option<JsonFormat>,
+    ) -> String {
+        let body = self.body(model, messages, format);
+
+        let mut cmd = format!("curl -X POST \"{}\"", self.url());
+        cmd.push_str(" \\\n  -H \"Content-Type: application/json\"");
+        if let Some(api_key) = &self.config.api_key {
+            cmd.push_str(&format!(" \\\n  -H \"Authorization: Bearer {}\"", api_key));
+        }
+        cmd.push_str(&format!(" \\\n  -d '{}'", body));
+        cmd
+    }
+}
@@ -0,0 +1,411 @@
+use crate::config::LlmProviderConfig;
+use crate::llm::{Message, estimate_tokens};
+use anyhow::{Context, Result};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+const WINDOW: Duration = Duration::from_secs(60);
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// The HTTP client providers send their requests through, with the
+/// provider's timeout, retries and rate limits.
+#[derive(Clone)]
+pub struct Transport {
+    client: reqwest::Client,
+    provider_id: String,
+    max_retries: u32,
+    limiter: Arc<RateLimiter>,
+}
+
+impl Transport {
+    pub fn new(config: &LlmProviderConfig) -> Self {
+        let timeout = Duration::try_from_secs_f32(config.timeout.unwrap_or(300.0))
+            .unwrap_or(Duration::from_secs(300));
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        Self {
+            client,
+            provider_id: config.id.clone(),
+            max_retries: config.max_retries.unwrap_or(5),
+            limiter: RateLimiter::for_provider(config),
+        }
+    }
+
+    /// For building requests to pass to `send`.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Sends a request once the rate limiter allows it, retrying timeouts,
+    /// rate limits and server errors with backoff or as long as the server
+    /// asks. Other error responses are returned for the caller to report.
+    pub async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        messages: &[Message],
+    ) -> Result<reqwest::Response> {
+        let tokens = estimate_tokens(messages);
+        let mut attempt = 0;
+
+        loop {
+            self.limiter.acquire(tokens).await;
+            let attempt_request = request.try_clone().context("Failed to clone LLM request")?;
+            let delay = match attempt_request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    if let Some(reset) = rate_limit_reset(response.headers()) {
+                        self.limiter.pause(reset);
+                    }
+                    return Ok(response);
+                }
+                Ok(response) if is_transient(response.status()) && attempt < self.max_retries => {
+                    let headers = response.headers();
+                    let delay = match retry_after(headers).or_else(|| rate_limit_reset(headers)) {
+                        // Hold back the other windows too, not just this one
+                        Some(delay) => {
+                            self.limiter.pause(delay);
+                            delay
+                        }
+                        None => backoff(attempt),
+                    };
+                    log::warn!(
+                        "{} returned {}, retrying in {:.1}s",
+                        self.provider_id,
+                        response.status(),
+                        delay.as_secs_f32()
+                    );
+                    delay
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if (e.is_timeout() || e.is_connect()) && attempt < self.max_retries => {
+                    let delay = backoff(attempt);
+                    log::warn!(
+                        "Request to {} failed ({}), retrying in {:.1}s",
+                        self.provider_id,
+                        e,
+                        delay.as_secs_f32()
+                    );
+                    delay
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Request to {} failed", self.provider_id));
+                }
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Statuses worth retrying: timeouts, rate limits, server errors and
+/// Anthropic's 529 "overloaded".
+fn is_transient(status: reqwest::StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+/// Jittered exponential backoff: a random delay between half and all of
+/// `BASE_DELAY * 2^attempt`, capped at `MAX_DELAY`.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_DELAY);
+    // Each RandomState is seeded differently, which is all the randomness
+    // jitter needs
+    let random = RandomState::new().build_hasher().finish();
+    let fraction = 0.5 + (random % 1000) as f64 / 2000.0;
+    delay.mul_f64(fraction)
+}
+
+/// How long the server asks us to wait: `retry-after-ms`, or `Retry-After`
+/// in whole seconds or as an HTTP date, capped at `MAX_DELAY`.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let ms = header("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok());
+    if let Some(ms) = ms.filter(|&ms| ms >= 0.0) {
+        // Too large for a Duration, infinite included
+        let delay = Duration::try_from_secs_f64(ms / 1000.0).unwrap_or(MAX_DELAY);
+        return Some(delay.min(MAX_DELAY));
+    }
+    let value = header("retry-after")?.trim();
+    let delay = if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        Duration::from_secs(value.parse().unwrap_or(u64::MAX))
+    } else {
+        let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+        until(date.with_timezone(&chrono::Utc))
+    };
+    Some(delay.min(MAX_DELAY))
+}
+
+/// How long until an exhausted rate limit resets, from OpenAI's
+/// `x-ratelimit-*` or Anthropic's `anthropic-ratelimit-*` headers, capped at
+/// `MAX_DELAY`.
+fn rate_limit_reset(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    const LIMITS: &[(&str, &str)] = &[
+        (
+            "x-ratelimit-remaining-requests",
+            "x-ratelimit-reset-requests",
+        ),
+        ("x-ratelimit-remaining-tokens", "x-ratelimit-reset-tokens"),
+        (
+            "anthropic-ratelimit-requests-remaining",
+            "anthropic-ratelimit-requests-reset",
+        ),
+        (
+            "anthropic-ratelimit-tokens-remaining",
+            "anthropic-ratelimit-tokens-reset",
+        ),
+    ];
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    LIMITS
+        .iter()
+        .filter(|(remaining, _)| header(remaining).is_some_and(|v| v.trim() == "0"))
+        .filter_map(|(_, reset)| {
+            let value = header(reset)?.trim();
+            parse_duration(value).or_else(|| {
+                let date = chrono::DateTime::parse_from_rfc3339(value).ok()?;
+                Some(until(date.with_timezone(&chrono::Utc)))
+            })
+        })
+        .max()
+        .map(|delay| delay.min(MAX_DELAY))
+}
+
+fn until(time: chrono::DateTime<chrono::Utc>) -> Duration {
+    (time - chrono::Utc::now()).to_std().unwrap_or_default()
+}
+
+/// Parses durations like `1s`, `6m0s`, `20ms` or `1h2m3.5s`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        total += number
+            * match &rest[..unit_len] {
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                "ms" => 0.001,
+                _ => return None,
+            };
+        rest = &rest[unit_len..];
+    }
+    Some(Duration::try_from_secs_f64(total).unwrap_or(Duration::MAX))
+}
+
+#[derive(Default)]
+struct LimiterState {
+    sent: VecDeque<(Instant, u64)>, // requests in the last minute and their tokens
+    paused_until: Option<Instant>,
+}
+
+/// Keeps requests to a provider under its requests- and tokens-per-minute
+/// limits, and holds them back while the provider says a limit is exhausted.
+struct RateLimiter {
+    requests_per_minute: Option<u32>,
+    tokens_per_minute: Option<u32>,
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> Self {
+        Self {
+            requests_per_minute,
+            tokens_per_minute,
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+
+    /// The limiter shared by every client of the same provider, so separate
+    /// clients for translating, editing and briefs draw on the same budget.
+    /// Configs that differ in endpoint or limits get limiters of their own.
+    fn for_provider(config: &LlmProviderConfig) -> Arc<Self> {
+        type Key = (String, Option<String>, Option<u32>, Option<u32>);
+        static LIMITERS: OnceLock<Mutex<HashMap<Key, Arc<RateLimiter>>>> = OnceLock::new();
+        let key = (
+            config.id.clone(),
+            config.base_url.clone(),
+            config.requests_per_minute,
+            config.tokens_per_minute,
+        );
+        let mut limiters = LIMITERS.get_or_init(Default::default).lock().unwrap();
+        limiters
+            .entry(key)
+            .or_insert_with(|| {
+                Arc::new(Self::new(
+                    config.requests_per_minute,
+                    config.tokens_per_minute,
+                ))
+            })
+            .clone()
+    }
+
+    /// Waits until a request of `tokens` fits in the limits, then counts it.
+    async fn acquire(&self, tokens: u64) {
+        loop {
+            match self.reserve(tokens, Instant::now()) {
+                Ok(()) => return,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    /// Holds back every request for `duration`.
+    fn pause(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut state = self.state.lock().unwrap();
+        if state.paused_until.is_none_or(|paused| paused < until) {
+            state.paused_until = Some(until);
+        }
+    }
+
+    /// Counts a request sent at `now`, or returns how long to wait first.
+    fn reserve(&self, tokens: u64, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(paused_until) = state.paused_until.filter(|&t| t > now) {
+            return Err(paused_until - now);
+        }
+        while state.sent.front().is_some_and(|&(t, _)| now - t >= WINDOW) {
+            state.sent.pop_front();
+        }
+
+        let over_requests = self
+            .requests_per_minute
+            .is_some_and(|rpm| state.sent.len() >= rpm.max(1) as usize);
+        // A request larger than the whole budget still goes out on its own
+        let used_tokens: u64 = state.sent.iter().map(|&(_, t)| t).sum();
+        let over_tokens = self
+            .tokens_per_minute
+            .is_some_and(|tpm| !state.sent.is_empty() && used_tokens + tokens > tpm as u64);
+        if over_requests || over_tokens {
+            let (oldest, _) = state.sent[0];
+            return Err(WINDOW - (now - oldest));
+        }
+
+        state.sent.push_back((now, tokens));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let seconds = |s| start + Duration::from_secs(s);
+
+        let limiter = RateLimiter::new(Some(2), None);
+        assert!(limiter.reserve(100, seconds(0)).is_ok());
+        assert!(limiter.reserve(100, seconds(10)).is_ok());
+        // A third request in the same minute waits for the first to age out
+        assert_eq!(
+            limiter.reserve(100, seconds(20)),
+            Err(Duration::from_secs(40))
+        );
+        assert!(limiter.reserve(100, seconds(60)).is_ok());
+
+        let limiter = RateLimiter::new(None, Some(1000));
+        assert!(limiter.reserve(600, seconds(0)).is_ok());
+        assert_eq!(
+            limiter.reserve(600, seconds(10)),
+            Err(Duration::from_secs(50))
+        );
+        // A request over the whole budget goes out alone
+        assert!(limiter.reserve(2000, seconds(60)).is_ok());
+
+        limiter.pause(Duration::from_secs(30));
+        assert!(limiter.reserve(1, Instant::now()).is_err());
+
+        let config: LlmProviderConfig =
+            serde_yaml::from_str("id: openai\napi_type: OpenAI\nrequests_per_minute: 10").unwrap();
+        let shared = RateLimiter::for_provider(&config);
+        assert!(Arc::ptr_eq(&shared, &RateLimiter::for_provider(&config)));
+        let stricter = LlmProviderConfig {
+            requests_per_minute: Some(2),
+            ..config
+        };
+        let own = RateLimiter::for_provider(&stricter);
+        assert!(!Arc::ptr_eq(&shared, &own));
+        assert_eq!(own.requests_per_minute, Some(2));
+    }
+
+    #[test]
+    fn test_rate_limit_headers() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("retry-after", "2".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(2)));
+
+        headers.insert("x-ratelimit-remaining-requests", "5".parse().unwrap());
+        headers.insert("x-ratelimit-reset-requests", "1s".parse().unwrap());
+        headers.insert("x-ratelimit-remaining-tokens", "0".parse().unwrap());
+        headers.insert("x-ratelimit-reset-tokens", "0m30.5s".parse().unwrap());
+        assert_eq!(
+            rate_limit_reset(&headers),
+            Some(Duration::from_secs_f64(30.5))
+        );
+        assert_eq!(
+            parse_duration("1m30.5s"),
+            Some(Duration::from_secs_f64(90.5))
+        );
+    }
+
+    #[test]
+    fn test_server_delays_are_capped() {
+        let retry_after_header = |name: &str, value: &str| {
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert(
+                reqwest::header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+            retry_after(&headers)
+        };
+        assert_eq!(retry_after_header("retry-after", "86400"), Some(MAX_DELAY));
+        assert_eq!(
+            retry_after_header("retry-after", "99999999999999999999"),
+            Some(MAX_DELAY)
+        );
+        // Only whole seconds are valid
+        assert_eq!(retry_after_header("retry-after", "1e400"), None);
+        assert_eq!(retry_after_header("retry-after", "1.5"), None);
+        assert_eq!(retry_after_header("retry-after", "-5"), None);
+        assert_eq!(
+            retry_after_header("retry-after", "Fri, 31 Dec 9999 23:59:59 GMT"),
+            Some(MAX_DELAY)
+        );
+        assert_eq!(
+            retry_after_header("retry-after-ms", "250"),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(retry_after_header("retry-after-ms", "inf"), Some(MAX_DELAY));
+        assert_eq!(retry_after_header("retry-after-ms", "NaN"), None);
+        assert_eq!(
+            retry_after_header("retry-after-ms", "1e300"),
+            Some(MAX_DELAY)
+        );
+
+        assert_eq!(
+            parse_duration(&format!("{}s", "9".repeat(400))),
+            Some(Duration::MAX)
+        );
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-ratelimit-remaining-requests", "0".parse().unwrap());
+        headers.insert("x-ratelimit-reset-requests", "24h0m0s".parse().unwrap());
+        assert_eq!(rate_limit_reset(&headers), Some(MAX_DELAY));
+    }
+}
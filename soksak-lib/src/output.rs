@@ -1,8 +1,9 @@
+use crate::config::{AssConfig, AssStyle, BilingualConfig, BilingualOrder, VttConfig};
 use crate::transcribe::TranscriptSegment;
 use crate::translate::TranslatedSegment;
 use anyhow::Result;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::Path;
 
 pub fn save_transcript_json(path: &Path, segments: &[TranscriptSegment]) -> Result<()> {
@@ -17,30 +18,435 @@ pub fn save_translation_json(path: &Path, segments: &[TranslatedSegment]) -> Res
     Ok(())
 }
 
-pub fn save_srt(path: &Path, segments: &[TranslatedSegment]) -> Result<()> {
-    let mut file = File::create(path)?;
+/// A single subtitle cue, independent of the output format.
+struct Cue {
+    start: i64, // centiseconds
+    end: i64,   // centiseconds
+    lines: Vec<String>,
+    original_lines: Vec<String>,
+}
+
+fn build_cues(segments: &[TranslatedSegment], bilingual: Option<&BilingualConfig>) -> Vec<Cue> {
+    segments
+        .iter()
+        .map(|segment| Cue {
+            start: segment.start,
+            end: segment.end,
+            lines: match bilingual {
+                Some(conf) => text_lines(&bilingual_text(segment, conf)),
+                None => text_lines(&segment.translated),
+            },
+            original_lines: text_lines(&segment.original),
+        })
+        .collect()
+}
+
+fn bilingual_text(segment: &TranslatedSegment, conf: &BilingualConfig) -> String {
+    let translated = segment.translated.trim();
+    let original = segment.original.trim();
+    if original.is_empty() || original == translated {
+        return translated.to_string();
+    }
+    if translated.is_empty() {
+        return original.to_string();
+    }
+
+    let separator = conf.separator.as_deref().unwrap_or("\n");
+    match conf.order.unwrap_or_default() {
+        BilingualOrder::TranslatedFirst => format!("{}{}{}", translated, separator, original),
+        BilingualOrder::OriginalFirst => format!("{}{}{}", original, separator, translated),
+    }
+}
+
+fn text_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
 
-    for (i, segment) in segments.iter().enumerate() {
-        writeln!(file, "{}", i + 1)?;
+/// Saves SRT subtitles. With `bilingual`, each cue also carries the original text.
+pub fn save_srt(
+    path: &Path,
+    segments: &[TranslatedSegment],
+    bilingual: Option<&BilingualConfig>,
+) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_srt(&mut file, segments, bilingual)?;
+    file.flush()?;
+    Ok(())
+}
+
+pub fn write_srt<W: Write>(
+    w: &mut W,
+    segments: &[TranslatedSegment],
+    bilingual: Option<&BilingualConfig>,
+) -> Result<()> {
+    // A cue without text would end at its blank first line and throw off
+    // the numbering of the ones after it
+    let cues = build_cues(segments, bilingual);
+    for (i, cue) in cues.iter().filter(|cue| !cue.lines.is_empty()).enumerate() {
+        writeln!(w, "{}", i + 1)?;
         writeln!(
-            file,
+            w,
+            "{} --> {}",
+            format_timestamp(cue.start),
+            format_timestamp(cue.end)
+        )?;
+        for line in &cue.lines {
+            writeln!(w, "{}", line)?;
+        }
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+/// Saves WebVTT subtitles. Each entry of `notes` is written as a `NOTE` block
+/// right after the header, e.g. to record the source file and languages.
+pub fn save_vtt(
+    path: &Path,
+    segments: &[TranslatedSegment],
+    conf: &VttConfig,
+    bilingual: Option<&BilingualConfig>,
+    notes: &[String],
+) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_vtt(&mut file, segments, conf, bilingual, notes)?;
+    file.flush()?;
+    Ok(())
+}
+
+pub fn write_vtt<W: Write>(
+    w: &mut W,
+    segments: &[TranslatedSegment],
+    conf: &VttConfig,
+    bilingual: Option<&BilingualConfig>,
+    notes: &[String],
+) -> Result<()> {
+    writeln!(w, "WEBVTT")?;
+    writeln!(w)?;
+
+    for note in notes {
+        // A NOTE block ends at the first blank line and must not contain "-->"
+        let note = note
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+            .replace("-->", "->");
+        if note.is_empty() {
+            continue;
+        }
+        writeln!(w, "NOTE {}", note)?;
+        writeln!(w)?;
+    }
+
+    let settings = conf.cue_settings();
+
+    // A cue without text would end at its blank first line and throw off
+    // the numbering of the ones after it
+    let cues = build_cues(segments, bilingual);
+    for (i, cue) in cues.iter().filter(|cue| !cue.lines.is_empty()).enumerate() {
+        writeln!(w, "{}", i + 1)?;
+        write!(
+            w,
             "{} --> {}",
-            format_timestamp(segment.start),
-            format_timestamp(segment.end)
+            format_vtt_timestamp(cue.start),
+            format_vtt_timestamp(cue.end)
+        )?;
+        if !settings.is_empty() {
+            write!(w, " {}", settings)?;
+        }
+        writeln!(w)?;
+        for line in &cue.lines {
+            writeln!(w, "{}", escape_vtt_text(line))?;
+        }
+        writeln!(w)?;
+    }
+
+    Ok(())
+}
+
+fn escape_vtt_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Saves Advanced SubStation Alpha subtitles. The translation and, unless
+/// disabled, the original text are emitted as separately styled events.
+pub fn save_ass(path: &Path, segments: &[TranslatedSegment], conf: &AssConfig) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_ass(&mut file, segments, conf)?;
+    file.flush()?;
+    Ok(())
+}
+
+pub fn write_ass<W: Write>(
+    w: &mut W,
+    segments: &[TranslatedSegment],
+    conf: &AssConfig,
+) -> Result<()> {
+    let show_original = conf.show_original.unwrap_or(true);
+
+    writeln!(w, "[Script Info]")?;
+    writeln!(w, "; Script generated by soksak")?;
+    writeln!(w, "ScriptType: v4.00+")?;
+    writeln!(w, "PlayResX: {}", conf.play_res_x.unwrap_or(1920))?;
+    writeln!(w, "PlayResY: {}", conf.play_res_y.unwrap_or(1080))?;
+    writeln!(w, "WrapStyle: 0")?;
+    writeln!(w, "ScaledBorderAndShadow: yes")?;
+    writeln!(w)?;
+
+    writeln!(w, "[V4+ Styles]")?;
+    writeln!(
+        w,
+        "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+         BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+         BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding"
+    )?;
+    let translated_style = conf.translated_style.clone().unwrap_or_default();
+    writeln!(
+        w,
+        "{}",
+        ass_style_line("Translated", &translated_style, 54, 2, 40)
+    )?;
+    if show_original {
+        let original_style = conf.original_style.clone().unwrap_or_default();
+        writeln!(
+            w,
+            "{}",
+            ass_style_line("Original", &original_style, 36, 8, 30)
         )?;
-        writeln!(file, "{}", segment.translated)?;
-        writeln!(file)?;
+    }
+    writeln!(w)?;
+
+    writeln!(w, "[Events]")?;
+    writeln!(
+        w,
+        "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text"
+    )?;
+    for cue in build_cues(segments, None) {
+        let start = format_ass_timestamp(cue.start);
+        let end = format_ass_timestamp(cue.end);
+
+        if !cue.lines.is_empty() {
+            writeln!(
+                w,
+                "Dialogue: 0,{},{},Translated,,0,0,0,,{}",
+                start,
+                end,
+                escape_ass_text(&cue.lines)
+            )?;
+        }
+        if show_original && !cue.original_lines.is_empty() {
+            writeln!(
+                w,
+                "Dialogue: 0,{},{},Original,,0,0,0,,{}",
+                start,
+                end,
+                escape_ass_text(&cue.original_lines)
+            )?;
+        }
     }
 
     Ok(())
 }
 
+fn ass_style_line(
+    name: &str,
+    style: &AssStyle,
+    default_font_size: u32,
+    default_alignment: u8,
+    default_margin_v: u32,
+) -> String {
+    let flag = |b: Option<bool>| if b.unwrap_or(false) { -1 } else { 0 };
+
+    format!(
+        "Style: {},{},{},{},&H000000FF,{},{},{},{},0,0,100,100,0,0,1,{},{},{},{},{},{},1",
+        name,
+        style.font_name.as_deref().unwrap_or("Arial"),
+        style.font_size.unwrap_or(default_font_size),
+        ass_colour(style.primary_colour.as_deref().unwrap_or("&H00FFFFFF")),
+        ass_colour(style.outline_colour.as_deref().unwrap_or("&H00000000")),
+        ass_colour(style.back_colour.as_deref().unwrap_or("&H80000000")),
+        flag(style.bold),
+        flag(style.italic),
+        style.outline.unwrap_or(2.0),
+        style.shadow.unwrap_or(1.0),
+        style.alignment.unwrap_or(default_alignment),
+        style.margin_l.unwrap_or(40),
+        style.margin_r.unwrap_or(40),
+        style.margin_v.unwrap_or(default_margin_v),
+    )
+}
+
+/// Converts `#RRGGBB`/`#RRGGBBAA` into ASS `&HAABBGGRR` (alpha 00 = opaque).
+/// Anything else is passed through unchanged.
+fn ass_colour(colour: &str) -> String {
+    let Some(hex) = colour.strip_prefix('#') else {
+        return colour.to_string();
+    };
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return colour.to_string();
+    }
+
+    let (r, g, b) = (&hex[0..2], &hex[2..4], &hex[4..6]);
+    let alpha = match hex.get(6..8) {
+        Some(a) => 255 - u8::from_str_radix(a, 16).unwrap_or(255),
+        None => 0,
+    };
+    format!("&H{:02X}{}{}{}", alpha, b, g, r).to_uppercase()
+}
+
+fn escape_ass_text(lines: &[String]) -> String {
+    lines
+        .iter()
+        .map(|line| line.replace('{', "(").replace('}', ")"))
+        .collect::<Vec<_>>()
+        .join("\\N")
+}
+
+/// Formats centiseconds as an ASS timestamp (`0:00:00.00`).
+pub fn format_ass_timestamp(cs: i64) -> String {
+    let cs = cs.max(0);
+    let hours = cs / 360000;
+    let minutes = (cs % 360000) / 6000;
+    let seconds = (cs % 6000) / 100;
+    let centis = cs % 100;
+
+    format!("{}:{:02}:{:02}.{:02}", hours, minutes, seconds, centis)
+}
+
+/// Formats centiseconds as an SRT timestamp (`00:00:00,000`).
 pub fn format_timestamp(cs: i64) -> String {
-    let ms = cs * 10;
+    format_timestamp_with_separator(cs, ',')
+}
+
+/// Formats centiseconds as a WebVTT timestamp (`00:00:00.000`).
+pub fn format_vtt_timestamp(cs: i64) -> String {
+    format_timestamp_with_separator(cs, '.')
+}
+
+fn format_timestamp_with_separator(cs: i64, separator: char) -> String {
+    let ms = cs.max(0) * 10;
     let hours = ms / 3600000;
     let minutes = (ms % 3600000) / 60000;
     let seconds = (ms % 60000) / 1000;
     let millis = ms % 1000;
 
-    format!("{:02}:{:02}:{:02},{:03}", hours, minutes, seconds, millis)
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        hours, minutes, seconds, separator, millis
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: i64, end: i64, translated: &str) -> TranslatedSegment {
+        TranslatedSegment {
+            start,
+            end,
+            original: String::new(),
+            translated: translated.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_timestamps() {
+        assert_eq!(format_timestamp(366123), "01:01:01,230");
+        assert_eq!(format_vtt_timestamp(366123), "01:01:01.230");
+    }
+
+    #[test]
+    fn test_write_srt() {
+        let segments = vec![
+            segment(0, 150, " Hello"),
+            segment(150, 200, "  \n"),
+            segment(200, 320, "World\n\n"),
+        ];
+        let mut buf = Vec::new();
+        write_srt(&mut buf, &segments, None).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "1\n00:00:00,000 --> 00:00:01,500\nHello\n\n\
+             2\n00:00:02,000 --> 00:00:03,200\nWorld\n\n"
+        );
+    }
+
+    #[test]
+    fn test_write_srt_bilingual() {
+        let segments = vec![TranslatedSegment {
+            original: "안녕하세요".to_string(),
+            ..segment(0, 150, "Hello")
+        }];
+
+        let mut buf = Vec::new();
+        write_srt(&mut buf, &segments, Some(&BilingualConfig::default())).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "1\n00:00:00,000 --> 00:00:01,500\nHello\n안녕하세요\n\n"
+        );
+
+        let conf = BilingualConfig {
+            order: Some(BilingualOrder::OriginalFirst),
+            separator: Some(" / ".to_string()),
+        };
+        let mut buf = Vec::new();
+        write_srt(&mut buf, &segments, Some(&conf)).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "1\n00:00:00,000 --> 00:00:01,500\n안녕하세요 / Hello\n\n"
+        );
+    }
+
+    #[test]
+    fn test_write_ass() {
+        let segments = vec![TranslatedSegment {
+            original: "こんにちは".to_string(),
+            ..segment(150, 366123, "Hello\n{world}")
+        }];
+        let conf = AssConfig {
+            original_style: Some(AssStyle {
+                primary_colour: Some("#FFCC00".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        write_ass(&mut buf, &segments, &conf).unwrap();
+        let out = String::from_utf8(buf).unwrap();
+
+        assert!(out.contains("Style: Original,Arial,36,&H0000CCFF,"));
+        assert!(
+            out.contains("Dialogue: 0,0:00:01.50,1:01:01.23,Translated,,0,0,0,,Hello\\N(world)\n")
+        );
+        assert!(out.contains("Dialogue: 0,0:00:01.50,1:01:01.23,Original,,0,0,0,,こんにちは\n"));
+    }
+
+    #[test]
+    fn test_write_vtt() {
+        let segments = vec![segment(0, 150, "Tom & <Jerry>"), segment(150, 200, "")];
+        let conf = VttConfig {
+            line: Some("85%".to_string()),
+            align: Some("center".to_string()),
+            ..Default::default()
+        };
+        let notes = vec!["Source: talk.mp4 --> ko".to_string()];
+        let mut buf = Vec::new();
+        write_vtt(&mut buf, &segments, &conf, None, &notes).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "WEBVTT\n\n\
+             NOTE Source: talk.mp4 -> ko\n\n\
+             1\n00:00:00.000 --> 00:00:01.500 line:85% align:center\n\
+             Tom &amp; &lt;Jerry&gt;\n\n"
+        );
+    }
 }
@@ -0,0 +1,510 @@
+use crate::config::{Language, LayoutConfig, LayoutRulesConfig};
+use crate::transcribe::{TranscriptSegment, TranscriptWord};
+use crate::translate::TranslatedSegment;
+
+/// Resolved subtitle layout constraints. Durations are in centiseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutRules {
+    pub max_chars_per_line: usize,
+    pub max_lines: usize,
+    pub max_cps: f32,
+    pub min_duration: i64,
+    pub max_duration: i64,
+}
+
+impl LayoutRules {
+    /// Built-in defaults, loosely following common broadcast guidelines.
+    /// CJK scripts get far fewer characters per line since each glyph is full-width.
+    pub fn defaults_for(lang: &Language) -> Self {
+        let (max_chars_per_line, max_cps) = match lang {
+            Language::Japanese => (13, 4.0),
+            Language::Chinese => (16, 9.0),
+            Language::Korean => (16, 12.0),
+            _ => (42, 17.0),
+        };
+
+        Self {
+            max_chars_per_line,
+            max_lines: 2,
+            max_cps,
+            min_duration: 83,
+            max_duration: 700,
+        }
+    }
+
+    /// Language defaults, overridden by the profile's general rules and then
+    /// by the rules configured for `lang`.
+    pub fn for_language(conf: &LayoutConfig, lang: &Language) -> Self {
+        let mut rules = Self::defaults_for(lang);
+        rules.merge(&conf.rules);
+        if let Some(overrides) = conf.languages.as_ref().and_then(|l| l.get(lang)) {
+            rules.merge(overrides);
+        }
+        rules
+    }
+
+    fn merge(&mut self, conf: &LayoutRulesConfig) {
+        if let Some(v) = conf.max_chars_per_line {
+            self.max_chars_per_line = v.max(1);
+        }
+        if let Some(v) = conf.max_lines {
+            self.max_lines = v.max(1);
+        }
+        if let Some(v) = conf.max_cps {
+            self.max_cps = v;
+        }
+        if let Some(v) = conf.min_duration {
+            self.min_duration = (v * 100.0).round() as i64;
+        }
+        if let Some(v) = conf.max_duration {
+            self.max_duration = (v * 100.0).round() as i64;
+        }
+    }
+
+    fn max_chars_per_cue(&self) -> usize {
+        self.max_chars_per_line * self.max_lines
+    }
+}
+
+struct Piece {
+    start: i64,
+    end: i64,
+    text: String,
+}
+
+/// Splits over-long cues, wraps lines and adjusts timing of translated segments.
+/// When a cue is split, its original text is split into the same number of parts.
+pub fn layout_translation(
+    segments: Vec<TranslatedSegment>,
+    rules: &LayoutRules,
+) -> Vec<TranslatedSegment> {
+    let mut pieces = Vec::new();
+    let mut originals = Vec::new();
+
+    for segment in segments {
+        let split = split_cue(segment.start, segment.end, &segment.translated, rules);
+        originals.extend(split_into(&normalize(&segment.original), split.len()));
+        pieces.extend(split);
+    }
+
+    adjust_timing(&mut pieces, rules);
+
+    pieces
+        .into_iter()
+        .zip(originals)
+        .map(|(piece, original)| TranslatedSegment {
+            start: piece.start,
+            end: piece.end,
+            original,
+            translated: piece.text,
+        })
+        .collect()
+}
+
+/// Same as [`layout_translation`] for untranslated transcripts.
+pub fn layout_transcript(
+    segments: Vec<TranscriptSegment>,
+    rules: &LayoutRules,
+) -> Vec<TranscriptSegment> {
+    let mut pieces = Vec::new();
+    let mut words = Vec::new();
+    let mut metadata = Vec::new();
+    for segment in segments {
+        let split = split_cue(segment.start, segment.end, &segment.text, rules);
+        metadata.extend(std::iter::repeat_n(
+            (segment.quality, segment.flagged),
+            split.len(),
+        ));
+        words.extend(distribute_words(segment.words, &split));
+        pieces.extend(split);
+    }
+
+    adjust_timing(&mut pieces, rules);
+
+    pieces
+        .into_iter()
+        .zip(words)
+        .zip(metadata)
+        .map(|((piece, words), (quality, flagged))| TranscriptSegment {
+            start: piece.start,
+            end: piece.end,
+            text: piece.text,
+            words,
+            quality,
+            flagged,
+        })
+        .collect()
+}
+
+/// Assigns each word to the piece containing the word's midpoint.
+fn distribute_words(words: Vec<TranscriptWord>, pieces: &[Piece]) -> Vec<Vec<TranscriptWord>> {
+    let mut out = vec![Vec::new(); pieces.len()];
+    for word in words {
+        let mid = (word.start + word.end) / 2;
+        let idx = pieces
+            .iter()
+            .position(|p| mid < p.end)
+            .unwrap_or(pieces.len() - 1);
+        out[idx].push(word);
+    }
+    out
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Splits one cue so that every part fits in `max_lines` lines and, where
+/// the text has room to break, lasts no longer than `max_duration` and can be
+/// read within it at `max_cps`. The time span is distributed proportionally
+/// to the number of characters.
+fn split_cue(start: i64, end: i64, text: &str, rules: &LayoutRules) -> Vec<Piece> {
+    let text = normalize(text);
+    let span = (end - start).max(0);
+
+    let len = text.chars().count();
+    let mut wanted = 1;
+    if rules.max_duration > 0 {
+        let longest = span.max(reading_time(len, rules)) as u64;
+        wanted = longest.div_ceil(rules.max_duration as u64).max(1) as usize;
+    }
+    // Only split where a word or clause ends, never inside a word
+    let chars: Vec<char> = text.chars().collect();
+    let breaks = (1..chars.len())
+        .filter(|&i| break_score(&chars, i).is_some_and(|score| score >= 1))
+        .count();
+    let wanted = wanted.min(breaks + 1);
+
+    let mut chunks = split_to_fit(&text, rules.max_chars_per_cue());
+    if chunks.len() < wanted {
+        chunks = split_into(&text, wanted);
+        chunks.retain(|chunk| !chunk.is_empty());
+    }
+    let total: usize = chunks.iter().map(|c| c.chars().count()).sum();
+
+    if total == 0 || chunks.len() < 2 {
+        return vec![Piece {
+            start,
+            end,
+            text: wrap_lines(&text, rules.max_chars_per_line),
+        }];
+    }
+
+    let mut pieces = Vec::with_capacity(chunks.len());
+    let mut consumed = 0;
+    for chunk in chunks {
+        let piece_start = start + span * consumed as i64 / total as i64;
+        consumed += chunk.chars().count();
+        let piece_end = start + span * consumed as i64 / total as i64;
+
+        pieces.push(Piece {
+            start: piece_start,
+            end: piece_end,
+            text: wrap_lines(&chunk, rules.max_chars_per_line),
+        });
+    }
+
+    pieces
+}
+
+fn wrap_lines(text: &str, max_chars_per_line: usize) -> String {
+    split_to_fit(text, max_chars_per_line).join("\n")
+}
+
+/// Centiseconds needed to read `chars` characters at `max_cps`.
+fn reading_time(chars: usize, rules: &LayoutRules) -> i64 {
+    if rules.max_cps > 0.0 {
+        (chars as f32 / rules.max_cps * 100.0).ceil() as i64
+    } else {
+        0
+    }
+}
+
+/// Enforces reading speed and duration limits. Short cues are extended into
+/// the following gap but never overlap the next cue.
+fn adjust_timing(pieces: &mut [Piece], rules: &LayoutRules) {
+    for i in 0..pieces.len() {
+        let next_start = pieces.get(i + 1).map(|p| p.start);
+        let piece = &mut pieces[i];
+
+        let chars = piece.text.chars().filter(|c| *c != '\n').count();
+        let needed = reading_time(chars, rules).max(rules.min_duration);
+
+        // A max_duration of 0 sets no limit, as in `split_cue`
+        let max_duration = (rules.max_duration > 0).then_some(rules.max_duration);
+        let mut end = match max_duration {
+            Some(max_duration) => piece.end.min(piece.start + max_duration),
+            None => piece.end,
+        };
+        if end - piece.start < needed {
+            end = piece.start + max_duration.map_or(needed, |m| needed.min(m));
+            if let Some(next_start) = next_start {
+                end = end.min(next_start.max(piece.end));
+            }
+        }
+        piece.end = end;
+    }
+}
+
+/// Greedily splits `text` into balanced chunks of at most `cap` characters,
+/// preferring punctuation, then spaces, then CJK character boundaries.
+fn split_to_fit(text: &str, cap: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut chunks = Vec::new();
+    let mut rest = &chars[..];
+
+    while rest.len() > cap {
+        let pieces = rest.len().div_ceil(cap);
+        let target = rest.len() / pieces;
+        let pos = best_break(rest, target, cap);
+
+        chunks.push(collect_trimmed(&rest[..pos]));
+        rest = trim_start(&rest[pos..]);
+    }
+    if !rest.is_empty() || chunks.is_empty() {
+        chunks.push(collect_trimmed(rest));
+    }
+
+    chunks
+}
+
+/// Splits `text` into exactly `n` parts of similar length (some may be empty).
+fn split_into(text: &str, n: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut parts = Vec::with_capacity(n);
+    let mut rest = &chars[..];
+
+    for remaining in (2..=n).rev() {
+        if rest.len() < 2 {
+            break;
+        }
+        let pos = best_break(rest, rest.len() / remaining, rest.len() - 1);
+        parts.push(collect_trimmed(&rest[..pos]));
+        rest = trim_start(&rest[pos..]);
+    }
+    parts.push(collect_trimmed(rest));
+    parts.resize(n, String::new());
+
+    parts
+}
+
+/// Picks the split position (index of the first char of the right part) in
+/// `1..=limit`, trading distance from `target` against boundary quality.
+fn best_break(chars: &[char], target: usize, limit: usize) -> usize {
+    let limit = limit.min(chars.len() - 1).max(1);
+    let target = target.clamp(1, limit);
+
+    (1..=limit)
+        .filter_map(|i| break_score(chars, i).map(|score| (i, score)))
+        .map(|(i, score)| {
+            let distance = i.abs_diff(target) as f32 / target as f32;
+            (i, distance - score as f32 * 0.35)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+        .unwrap_or(target)
+}
+
+/// Quality of a break before `chars[i]`, or `None` if breaking there is not allowed.
+fn break_score(chars: &[char], i: usize) -> Option<u8> {
+    let prev = chars[i - 1];
+    let next = chars[i];
+
+    if prev == ' ' || is_no_break_before(next) || is_no_break_after(prev) {
+        return None;
+    }
+
+    let boundary = next == ' ' || is_wide(prev) || is_wide(next);
+    if boundary && is_sentence_end(prev) {
+        Some(4)
+    } else if boundary && is_clause_end(prev) {
+        Some(3)
+    } else if next == ' ' {
+        Some(2)
+    } else if is_cjk(prev) && is_cjk(next) {
+        Some(1)
+    } else if is_wide(prev) && is_wide(next) {
+        // Korean separates words with spaces; breaking inside a word is a last resort
+        Some(0)
+    } else {
+        None
+    }
+}
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…' | '。' | '！' | '？' | '．')
+}
+
+fn is_clause_end(c: char) -> bool {
+    matches!(c, ',' | ';' | ':' | '、' | '，' | '；' | '：')
+}
+
+fn is_no_break_before(c: char) -> bool {
+    is_sentence_end(c)
+        || is_clause_end(c)
+        || matches!(
+            c,
+            ')' | ']' | '」' | '』' | '）' | '】' | '〕' | '〉' | '》' | '”' | '’' | 'ー' | '々'
+        )
+        || matches!(
+            c,
+            'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ' | 'っ' | 'ゃ' | 'ゅ' | 'ょ' | 'ゎ'
+        )
+        || matches!(
+            c,
+            'ァ' | 'ィ' | 'ゥ' | 'ェ' | 'ォ' | 'ッ' | 'ャ' | 'ュ' | 'ョ' | 'ヮ' | 'ヵ' | 'ヶ'
+        )
+}
+
+fn is_no_break_after(c: char) -> bool {
+    matches!(
+        c,
+        '(' | '[' | '「' | '『' | '（' | '【' | '〔' | '〈' | '《' | '“' | '‘'
+    )
+}
+
+/// Han, kana and full-width punctuation: scripts written without spaces.
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3000..=0x303F // CJK symbols and punctuation
+        | 0x3040..=0x30FF // Hiragana, Katakana
+        | 0x3400..=0x4DBF // CJK Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+        | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+        | 0xFF00..=0xFFEF // Half-width and full-width forms
+    )
+}
+
+fn is_hangul(c: char) -> bool {
+    matches!(c as u32, 0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF)
+}
+
+fn is_wide(c: char) -> bool {
+    is_cjk(c) || is_hangul(c)
+}
+
+fn collect_trimmed(chars: &[char]) -> String {
+    chars.iter().collect::<String>().trim().to_string()
+}
+
+fn trim_start(chars: &[char]) -> &[char] {
+    let skip = chars.iter().take_while(|c| c.is_whitespace()).count();
+    &chars[skip..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(max_chars_per_line: usize) -> LayoutRules {
+        LayoutRules {
+            max_chars_per_line,
+            ..LayoutRules::defaults_for(&Language::English)
+        }
+    }
+
+    #[test]
+    fn test_wraps_at_word_boundary() {
+        let segments = vec![TranscriptSegment {
+            start: 0,
+            end: 300,
+            text: " The quick brown fox jumps over the lazy dog".to_string(),
+            ..Default::default()
+        }];
+        let out = layout_transcript(segments, &rules(24));
+
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].text, "The quick brown fox\njumps over the lazy dog");
+    }
+
+    #[test]
+    fn test_splits_long_cue_at_punctuation() {
+        let segments = vec![TranslatedSegment {
+            start: 0,
+            end: 1000,
+            original: "First part here. Second part there.".to_string(),
+            translated: "今日はとても良い天気ですね。明日も晴れるといいですね。".to_string(),
+        }];
+        let rules = LayoutRules {
+            max_chars_per_line: 16,
+            max_lines: 1,
+            ..LayoutRules::defaults_for(&Language::Japanese)
+        };
+        let out = layout_translation(segments, &rules);
+
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].translated, "今日はとても良い天気ですね。");
+        assert_eq!(out[1].translated, "明日も晴れるといいですね。");
+        assert_eq!(out[0].original, "First part here.");
+        assert_eq!(out[1].original, "Second part there.");
+        assert_eq!(out[0].start, 0);
+        assert_eq!(out[0].end, out[1].start);
+        assert_eq!(out[1].end, 1000);
+    }
+
+    #[test]
+    fn test_korean_prefers_spaces() {
+        let chunks = split_to_fit("오늘은 정말 좋은 날씨네요 내일도 맑았으면 좋겠어요", 16);
+        assert_eq!(
+            chunks,
+            vec!["오늘은 정말 좋은 날씨네요", "내일도 맑았으면 좋겠어요"]
+        );
+    }
+
+    #[test]
+    fn test_splits_cue_longer_than_max_duration() {
+        let segments = vec![TranscriptSegment {
+            start: 0,
+            end: 1500,
+            text: "Well, I think that we should probably go home now.".to_string(),
+            ..Default::default()
+        }];
+        let out = layout_transcript(segments, &rules(42));
+
+        // 15 seconds of slow speech becomes three cues that cover all of it
+        assert_eq!(out.len(), 3);
+        assert_eq!(out[0].start, 0);
+        assert_eq!(out[2].end, 1500);
+        for pair in out.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        assert!(out.iter().all(|cue| cue.end - cue.start <= 700));
+
+        // Without a limit the cue is kept whole
+        let unlimited = LayoutRules {
+            max_duration: 0,
+            ..rules(42)
+        };
+        let segments = vec![TranscriptSegment {
+            start: 0,
+            end: 1500,
+            text: "Well, I think that we should probably go home now.".to_string(),
+            ..Default::default()
+        }];
+        let out = layout_transcript(segments, &unlimited);
+        assert_eq!(out.len(), 1);
+        assert_eq!((out[0].start, out[0].end), (0, 1500));
+    }
+
+    #[test]
+    fn test_extends_short_cue_without_overlap() {
+        let segments = vec![
+            TranscriptSegment {
+                start: 0,
+                end: 20,
+                text: "This needs more reading time".to_string(),
+                ..Default::default()
+            },
+            TranscriptSegment {
+                start: 100,
+                end: 1500,
+                text: "Ok".to_string(),
+                ..Default::default()
+            },
+        ];
+        let out = layout_transcript(segments, &rules(42));
+
+        assert_eq!(out[0].end, 100);
+        assert_eq!(out[1].end, 100 + 700);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::checkpoint;
+use crate::config::{AudioChannel, AudioStream, Language};
+use crate::transcribe::TranscriptSegment;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Bytes hashed from each end of the input for its fingerprint.
+const FINGERPRINT_BYTES: u64 = 1 << 20;
+
+/// Identifies an input file cheaply: size, modification time and a CRC32 of
+/// its first and last megabyte.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputFingerprint {
+    pub size: u64,
+    pub modified: Option<u64>, // seconds since the Unix epoch
+    pub crc32: u32,
+}
+
+impl InputFingerprint {
+    pub fn of(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        let mut crc = flate2::Crc::new();
+        let mut buf = Vec::new();
+        (&mut file).take(FINGERPRINT_BYTES).read_to_end(&mut buf)?;
+        crc.update(&buf);
+        if size > FINGERPRINT_BYTES * 2 {
+            buf.clear();
+            file.seek(SeekFrom::End(-(FINGERPRINT_BYTES as i64)))?;
+            file.read_to_end(&mut buf)?;
+            crc.update(&buf);
+        }
+
+        Ok(Self {
+            size,
+            modified,
+            crc32: crc.sum(),
+        })
+    }
+}
+
+/// What a finished transcript was made from, saved next to it so that
+/// `--resume` only reuses a transcript of the same input and settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptSource {
+    pub input: InputFingerprint,
+    pub model: String,
+    pub lang: Language,
+    pub audio_stream: Option<AudioStream>,
+    pub audio_channel: AudioChannel,
+    pub range_start: Option<i64>, // centiseconds
+    pub range_end: Option<i64>,   // centiseconds
+}
+
+impl TranscriptSource {
+    /// Loads the source at `path`, or `None` if it is missing or unreadable,
+    /// in which case the transcript can't be vouched for.
+    pub fn load(path: &Path) -> Option<Self> {
+        checkpoint::load(path).ok().flatten()
+    }
+}
+
+/// Everything that decides which segments a chunked transcription produces,
+/// so a checkpoint is only continued under the same settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkedSettings {
+    pub input: InputFingerprint,
+    pub model: PathBuf,
+    pub lang: Language,
+    pub audio_stream: Option<AudioStream>,
+    pub audio_channel: AudioChannel,
+    pub range_start: Option<i64>, // centiseconds
+    pub range_end: Option<i64>,   // centiseconds
+    pub chunk_length: i64,        // centiseconds
+    pub chunk_overlap: i64,       // centiseconds
+}
+
+/// Segments transcribed so far and where to continue, saved after every chunk.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscriptionCheckpoint {
+    #[serde(flatten)]
+    pub settings: ChunkedSettings,
+    pub position: i64, // centiseconds, start of the next chunk
+    pub segments: Vec<TranscriptSegment>,
+}
+
+impl TranscriptionCheckpoint {
+    /// Fails if the checkpoint was made from a different input or with
+    /// different settings.
+    pub fn validate(&self, settings: &ChunkedSettings) -> Result<()> {
+        let saved = &self.settings;
+        if saved.input != settings.input {
+            bail!("Input file has changed since the checkpoint was written; run without --resume");
+        }
+        if saved.model != settings.model {
+            bail!(
+                "Checkpoint was written with model {:?}, not {:?}; run without --resume",
+                saved.model,
+                settings.model
+            );
+        }
+        if saved.lang != settings.lang {
+            bail!(
+                "Checkpoint was written for language {}, not {}; run without --resume",
+                saved.lang,
+                settings.lang
+            );
+        }
+        if (&saved.audio_stream, saved.audio_channel)
+            != (&settings.audio_stream, settings.audio_channel)
+        {
+            bail!(
+                "Checkpoint was written for a different --audio-stream/--audio-channel; run without --resume"
+            );
+        }
+        if (saved.range_start, saved.range_end) != (settings.range_start, settings.range_end) {
+            bail!("Checkpoint was written for a different --start/--end; run without --resume");
+        }
+        if (saved.chunk_length, saved.chunk_overlap)
+            != (settings.chunk_length, settings.chunk_overlap)
+        {
+            bail!(
+                "Checkpoint was written with a different chunk length or overlap; run without --resume"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_checkpoint_round_trip_and_validation() {
+        let mut input = tempfile::NamedTempFile::new().unwrap();
+        input.write_all(b"some audio").unwrap();
+        let fingerprint = InputFingerprint::of(input.path()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("talk.transcript.checkpoint.json");
+        assert!(
+            checkpoint::load::<TranscriptionCheckpoint>(&path)
+                .unwrap()
+                .is_none()
+        );
+
+        let settings = ChunkedSettings {
+            input: fingerprint.clone(),
+            model: PathBuf::from("/models/ggml-large-v3.bin"),
+            lang: Language::English,
+            audio_stream: None,
+            audio_channel: AudioChannel::Mix,
+            range_start: Some(240000),
+            range_end: None,
+            chunk_length: 60000,
+            chunk_overlap: 200,
+        };
+        let saved = TranscriptionCheckpoint {
+            settings: settings.clone(),
+            position: 300000,
+            segments: vec![TranscriptSegment {
+                start: 240000,
+                end: 240300,
+                text: "Hello".to_string(),
+                ..Default::default()
+            }],
+        };
+        checkpoint::save(&saved, &path).unwrap();
+
+        let loaded: TranscriptionCheckpoint = checkpoint::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.position, 300000);
+        assert_eq!(loaded.segments[0].text, "Hello");
+        assert!(loaded.validate(&settings).is_ok());
+
+        let changes = [
+            ChunkedSettings {
+                model: PathBuf::from("/models/other.bin"),
+                ..settings.clone()
+            },
+            ChunkedSettings {
+                lang: Language::Japanese,
+                ..settings.clone()
+            },
+            ChunkedSettings {
+                audio_stream: Some(AudioStream::Index(1)),
+                ..settings.clone()
+            },
+            ChunkedSettings {
+                audio_channel: AudioChannel::Left,
+                ..settings.clone()
+            },
+            ChunkedSettings {
+                range_start: None,
+                ..settings.clone()
+            },
+            ChunkedSettings {
+                chunk_length: 30000,
+                ..settings.clone()
+            },
+        ];
+        for changed in &changes {
+            assert!(loaded.validate(changed).is_err(), "{:?}", changed);
+        }
+
+        input.write_all(b" and more").unwrap();
+        let changed = ChunkedSettings {
+            input: InputFingerprint::of(input.path()).unwrap(),
+            ..settings
+        };
+        assert!(loaded.validate(&changed).is_err());
+
+        checkpoint::remove(&path).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_transcript_source_matches_settings() {
+        let mut input = tempfile::NamedTempFile::new().unwrap();
+        input.write_all(b"some audio").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("talk.transcript.source.json");
+        assert!(TranscriptSource::load(&path).is_none());
+
+        let source = TranscriptSource {
+            input: InputFingerprint::of(input.path()).unwrap(),
+            model: "/models/ggml-large-v3.bin".to_string(),
+            lang: Language::English,
+            audio_stream: None,
+            audio_channel: AudioChannel::Mix,
+            range_start: None,
+            range_end: Some(60000),
+        };
+        checkpoint::save(&source, &path).unwrap();
+        assert_eq!(TranscriptSource::load(&path).as_ref(), Some(&source));
+
+        let other_lang = TranscriptSource {
+            lang: Language::Korean,
+            ..source.clone()
+        };
+        assert_ne!(TranscriptSource::load(&path), Some(other_lang));
+        let other_stream = TranscriptSource {
+            audio_stream: Some(AudioStream::Language("jpn".to_string())),
+            ..source.clone()
+        };
+        assert_ne!(TranscriptSource::load(&path), Some(other_stream));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(TranscriptSource::load(&path).is_none());
+    }
+}
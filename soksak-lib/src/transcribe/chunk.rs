@@ -0,0 +1,146 @@
+use crate::transcribe::TranscriptSegment;
+use crate::transcribe::hallucination::normalize;
+
+/// Shortest pause worth cutting at, in centiseconds.
+const MIN_SILENCE: i64 = 30;
+
+/// Roughly what fits in Whisper's 224-token prompt along with a user prompt.
+const MAX_PROMPT_CHARS: usize = 200;
+
+/// Finds where to end a window of `window_len` centiseconds, given the
+/// speech spans VAD found in it (relative to the window start). Cuts in the
+/// middle of the latest silence that ends the window no earlier than `min_cut`.
+pub(crate) fn find_cut(speech: &[(i64, i64)], window_len: i64, min_cut: i64) -> Option<i64> {
+    let mut gaps = Vec::with_capacity(speech.len() + 1);
+    let mut silence_start = 0;
+    for &(start, end) in speech {
+        gaps.push((silence_start, start));
+        silence_start = silence_start.max(end);
+    }
+    gaps.push((silence_start, window_len));
+
+    gaps.into_iter()
+        .filter(|(start, end)| end - start >= MIN_SILENCE)
+        .map(|(start, end)| (start + end) / 2)
+        .filter(|cut| (min_cut..=window_len).contains(cut))
+        .max()
+}
+
+/// Appends the segments of the next chunk, skipping what was already
+/// transcribed from the overlap between the two chunks.
+pub(crate) fn stitch_segments(
+    segments: &mut Vec<TranscriptSegment>,
+    chunk: Vec<TranscriptSegment>,
+) {
+    let Some(last) = segments.last() else {
+        segments.extend(chunk);
+        return;
+    };
+    let covered_until = last.end;
+    let recent: Vec<String> = segments
+        .iter()
+        .rev()
+        .take(3)
+        .map(|s| normalize(&s.text))
+        .collect();
+
+    for (i, mut segment) in chunk.into_iter().enumerate() {
+        let text = normalize(&segment.text);
+        if text.is_empty() || segment.end <= covered_until {
+            continue;
+        }
+
+        let overlaps = segment.start < covered_until;
+        let repeated = recent.iter().any(|r| !r.is_empty() && r.contains(&text));
+        // A chunk sometimes opens by repeating the prompt it was given
+        if repeated && (overlaps || (i == 0 && text == recent[0])) {
+            continue;
+        }
+
+        if overlaps {
+            segment.start = covered_until;
+            segment.words.retain(|w| w.end > covered_until);
+        }
+        segments.push(segment);
+    }
+}
+
+/// Builds the prompt for the next chunk from the user's prompt and the tail
+/// of what has been transcribed so far.
+pub(crate) fn carry_over_prompt(
+    initial_prompt: Option<&str>,
+    segments: &[TranscriptSegment],
+) -> Option<String> {
+    let mut tail = String::new();
+    for segment in segments.iter().rev() {
+        let text = segment.text.trim();
+        if tail.chars().count() + text.chars().count() > MAX_PROMPT_CHARS {
+            break;
+        }
+        tail = format!("{} {}", text, tail);
+    }
+    let tail = tail.trim();
+
+    match (initial_prompt, tail.is_empty()) {
+        (Some(prompt), false) => Some(format!("{} {}", prompt, tail)),
+        (Some(prompt), true) => Some(prompt.to_string()),
+        (None, false) => Some(tail.to_string()),
+        (None, true) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: i64, end: i64, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start,
+            end,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_find_cut() {
+        let speech = [(0, 4000), (4100, 50000), (50500, 58000)];
+
+        // Latest pause in the second half of a 60s window
+        assert_eq!(find_cut(&speech, 60000, 30000), Some(59000));
+        assert_eq!(find_cut(&speech, 58010, 30000), Some(50250));
+        // No pause long enough after `min_cut`
+        assert_eq!(find_cut(&[(0, 60000)], 60000, 30000), None);
+    }
+
+    #[test]
+    fn test_stitch_drops_overlap() {
+        let mut segments = vec![
+            segment(0, 300, "Hello there."),
+            segment(300, 600, "How are you?"),
+        ];
+        let chunk = vec![
+            segment(400, 600, "how are you"),
+            segment(550, 800, "I'm fine."),
+            segment(800, 900, "Thanks."),
+        ];
+        stitch_segments(&mut segments, chunk);
+
+        let texts: Vec<_> = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["Hello there.", "How are you?", "I'm fine.", "Thanks."]
+        );
+        assert_eq!(segments[2].start, 600);
+    }
+
+    #[test]
+    fn test_carry_over_prompt() {
+        let segments = vec![segment(0, 100, " First."), segment(100, 200, " Second.")];
+        assert_eq!(
+            carry_over_prompt(Some("Glossary: soksak."), &segments).as_deref(),
+            Some("Glossary: soksak. First. Second.")
+        );
+        assert_eq!(carry_over_prompt(None, &[]), None);
+    }
+}
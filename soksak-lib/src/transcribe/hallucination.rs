@@ -0,0 +1,215 @@
+use crate::config::{HallucinationAction, HallucinationConfig};
+use crate::transcribe::TranscriptSegment;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use std::io::Write;
+
+const DEFAULT_NO_SPEECH_THRESHOLD: f32 = 0.6;
+const DEFAULT_LOGPROB_THRESHOLD: f32 = -1.0;
+const DEFAULT_COMPRESSION_RATIO_THRESHOLD: f32 = 2.4;
+const DEFAULT_MAX_REPEATS: usize = 2;
+
+/// Lines Whisper is known to produce over silence or music, learned from
+/// video subtitles in its training data.
+const KNOWN_PHRASES: &[&str] = &[
+    "thank you for watching",
+    "thanks for watching",
+    "please subscribe",
+    "subtitles by the amara.org community",
+    "ご視聴ありがとうございました",
+    "チャンネル登録をお願いします",
+    "시청해주셔서 감사합니다",
+    "구독과 좋아요 부탁드립니다",
+    "谢谢观看",
+    "请不吝点赞 订阅 转发 打赏支持明镜与点点栏目",
+];
+
+#[derive(Debug, Clone)]
+pub struct FlaggedSegment {
+    pub start: i64, // centiseconds
+    pub end: i64,   // centiseconds
+    pub text: String,
+    pub reason: String,
+}
+
+/// Ratio of the UTF-8 size to the zlib-compressed size, as used by Whisper to
+/// detect repetition loops. Repetitive text compresses well and scores high.
+pub fn compression_ratio(text: &str) -> f32 {
+    if text.is_empty() {
+        return 0.0;
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let compressed = encoder
+        .write_all(text.as_bytes())
+        .and_then(|_| encoder.finish())
+        .map(|c| c.len())
+        .unwrap_or(text.len());
+
+    text.len() as f32 / compressed.max(1) as f32
+}
+
+/// Drops (or flags, depending on `conf.action`) segments that look hallucinated
+/// and returns the remaining segments together with what was caught.
+pub fn filter_hallucinations(
+    segments: Vec<TranscriptSegment>,
+    conf: &HallucinationConfig,
+) -> (Vec<TranscriptSegment>, Vec<FlaggedSegment>) {
+    let action = conf.action.unwrap_or_default();
+    let max_repeats = conf.max_repeats.unwrap_or(DEFAULT_MAX_REPEATS).max(1);
+    let phrases: Vec<String> = KNOWN_PHRASES
+        .iter()
+        .copied()
+        .chain(conf.phrases.iter().flatten().map(String::as_str))
+        .map(normalize)
+        .filter(|p| !p.is_empty())
+        .collect();
+
+    let mut kept = Vec::with_capacity(segments.len());
+    let mut flagged = Vec::new();
+    let mut previous = String::new();
+    let mut run = 0;
+
+    for mut segment in segments {
+        let normalized = normalize(&segment.text);
+        if !normalized.is_empty() && normalized == previous {
+            run += 1;
+        } else {
+            run = 1;
+            previous = normalized.clone();
+        }
+
+        let reason = if run > max_repeats {
+            Some(format!("repeated line ({} times in a row)", run))
+        } else {
+            check_segment(&segment, &normalized, &phrases, conf)
+        };
+
+        match reason {
+            Some(reason) => {
+                flagged.push(FlaggedSegment {
+                    start: segment.start,
+                    end: segment.end,
+                    text: segment.text.clone(),
+                    reason: reason.clone(),
+                });
+                if action == HallucinationAction::Flag {
+                    segment.flagged = Some(reason);
+                    kept.push(segment);
+                }
+            }
+            None => kept.push(segment),
+        }
+    }
+
+    (kept, flagged)
+}
+
+fn check_segment(
+    segment: &TranscriptSegment,
+    normalized: &str,
+    phrases: &[String],
+    conf: &HallucinationConfig,
+) -> Option<String> {
+    let no_speech_threshold = conf
+        .no_speech_threshold
+        .unwrap_or(DEFAULT_NO_SPEECH_THRESHOLD);
+    let logprob_threshold = conf.logprob_threshold.unwrap_or(DEFAULT_LOGPROB_THRESHOLD);
+    let compression_ratio_threshold = conf
+        .compression_ratio_threshold
+        .unwrap_or(DEFAULT_COMPRESSION_RATIO_THRESHOLD);
+
+    let ratio = segment
+        .quality
+        .map(|q| q.compression_ratio)
+        .unwrap_or_else(|| compression_ratio(segment.text.trim()));
+    if ratio > compression_ratio_threshold {
+        return Some(format!("repetition loop (compression ratio {:.2})", ratio));
+    }
+
+    // Engines without decoder statistics only get the text-based checks
+    let quality = segment.quality?;
+
+    if quality.no_speech_prob > no_speech_threshold && quality.avg_logprob < logprob_threshold {
+        return Some(format!(
+            "low confidence over silence (no_speech {:.2}, avg_logprob {:.2})",
+            quality.no_speech_prob, quality.avg_logprob
+        ));
+    }
+
+    let uncertain = quality.no_speech_prob > no_speech_threshold / 2.0
+        || quality.avg_logprob < logprob_threshold;
+    if uncertain && phrases.iter().any(|p| normalized.contains(p.as_str())) {
+        return Some("known hallucination phrase".to_string());
+    }
+
+    None
+}
+
+/// Lowercases and strips everything but letters and digits.
+pub(crate) fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcribe::SegmentQuality;
+
+    fn segment(text: &str, quality: Option<(f32, f32)>) -> TranscriptSegment {
+        TranscriptSegment {
+            start: 0,
+            end: 100,
+            text: text.to_string(),
+            quality: quality.map(|(avg_logprob, no_speech_prob)| SegmentQuality {
+                avg_logprob,
+                no_speech_prob,
+                compression_ratio: compression_ratio(text),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_drops_repeats_and_silence() {
+        let segments = vec![
+            segment("Hello there.", Some((-0.2, 0.1))),
+            segment("Again", Some((-0.3, 0.1))),
+            segment("again!", Some((-0.3, 0.1))),
+            segment("Again.", Some((-0.3, 0.1))),
+            segment(" Thank you for watching!", Some((-0.5, 0.4))),
+            segment("Mumble", Some((-1.5, 0.9))),
+        ];
+        let (kept, flagged) = filter_hallucinations(segments, &HallucinationConfig::default());
+
+        let kept: Vec<_> = kept.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(kept, vec!["Hello there.", "Again", "again!"]);
+        assert_eq!(flagged.len(), 3);
+        assert!(flagged[0].reason.starts_with("repeated line"));
+        assert_eq!(flagged[1].reason, "known hallucination phrase");
+        assert!(flagged[2].reason.starts_with("low confidence over silence"));
+    }
+
+    #[test]
+    fn test_flags_repetition_loop() {
+        let looped = "I'm sorry. ".repeat(20);
+        let conf = HallucinationConfig {
+            action: Some(HallucinationAction::Flag),
+            ..Default::default()
+        };
+        let (kept, flagged) = filter_hallucinations(vec![segment(&looped, None)], &conf);
+
+        assert_eq!(kept.len(), 1);
+        assert!(
+            kept[0]
+                .flagged
+                .as_deref()
+                .unwrap()
+                .starts_with("repetition loop")
+        );
+        assert_eq!(flagged.len(), 1);
+    }
+}
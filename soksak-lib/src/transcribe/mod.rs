@@ -1,10 +1,205 @@
+pub mod checkpoint;
+pub(crate) mod chunk;
+pub mod hallucination;
 pub mod whisper_cpp;
 #[cfg(feature = "apple")]
 pub mod whisperkit;
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct TranscriptSegment {
     pub start: i64, // centiseconds
     pub end: i64,   // centiseconds
     pub text: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<TranscriptWord>, // only with `word_timestamps`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<SegmentQuality>, // only from whisper_cpp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flagged: Option<String>, // reason, if the hallucination filter flagged it
+}
+
+/// Decoder statistics used to judge whether a segment is trustworthy.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct SegmentQuality {
+    pub avg_logprob: f32,
+    pub no_speech_prob: f32,
+    pub compression_ratio: f32,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TranscriptWord {
+    pub start: i64, // centiseconds
+    pub end: i64,   // centiseconds
+    pub text: String,
+    pub probability: f32,
+}
+
+/// A decoded token with its timing, as reported by the engine.
+pub(crate) struct TimedToken {
+    pub bytes: Vec<u8>,
+    pub start: i64, // centiseconds
+    pub end: i64,   // centiseconds
+    pub probability: f32,
+}
+
+/// Groups BPE tokens into words. A token starting with a space begins a new
+/// word; in scripts written without spaces (Han, kana) every token is a word.
+/// Tokens are joined as bytes since a multi-byte character can be split
+/// across tokens.
+pub(crate) fn group_tokens_into_words(tokens: &[TimedToken]) -> Vec<TranscriptWord> {
+    struct Pending {
+        bytes: Vec<u8>,
+        start: i64,
+        end: i64,
+        probabilities: Vec<f32>,
+    }
+
+    let finish = |w: Pending| {
+        let text = String::from_utf8_lossy(&w.bytes).trim().to_string();
+        let probability = w.probabilities.iter().sum::<f32>() / w.probabilities.len() as f32;
+        TranscriptWord {
+            start: w.start,
+            end: w.end,
+            text,
+            probability,
+        }
+    };
+
+    let mut words = Vec::new();
+    let mut current: Option<Pending> = None;
+
+    for token in tokens {
+        let Some(&first) = token.bytes.first() else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(&token.bytes);
+        let first_char = text.chars().next().unwrap_or(' ');
+        let continuation = (0x80..0xC0).contains(&first);
+        let is_punctuation = first_char.is_ascii_punctuation() && first_char != '\'';
+
+        let starts_word = match &current {
+            None => true,
+            Some(_) if continuation || is_punctuation => false,
+            Some(w) => {
+                let prev_char = String::from_utf8_lossy(&w.bytes).chars().last();
+                first == b' '
+                    || is_unspaced_script(first_char)
+                    || prev_char.is_some_and(is_unspaced_script)
+            }
+        };
+
+        if starts_word {
+            if let Some(w) = current.take() {
+                words.push(finish(w));
+            }
+            current = Some(Pending {
+                bytes: token.bytes.clone(),
+                start: token.start,
+                end: token.end,
+                probabilities: vec![token.probability],
+            });
+        } else if let Some(w) = current.as_mut() {
+            w.bytes.extend_from_slice(&token.bytes);
+            w.end = token.end.max(w.end);
+            w.probabilities.push(token.probability);
+        }
+    }
+    if let Some(w) = current {
+        words.push(finish(w));
+    }
+
+    words.retain(|w| !w.text.is_empty());
+    words
+}
+
+/// Shifts segments decoded from a time range back onto the input's timeline.
+pub(crate) fn offset_segments(segments: &mut [TranscriptSegment], offset: i64) {
+    if offset == 0 {
+        return;
+    }
+    for segment in segments {
+        segment.start += offset;
+        segment.end += offset;
+        for word in &mut segment.words {
+            word.start += offset;
+            word.end += offset;
+        }
+    }
+}
+
+fn is_unspaced_script(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF // Hiragana, Katakana
+        | 0x3400..=0x4DBF // CJK Extension A
+        | 0x4E00..=0x9FFF // CJK Unified Ideographs
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(text: &[u8], start: i64, end: i64, probability: f32) -> TimedToken {
+        TimedToken {
+            bytes: text.to_vec(),
+            start,
+            end,
+            probability,
+        }
+    }
+
+    #[test]
+    fn test_group_tokens_into_words() {
+        let tokens = vec![
+            token(b" Hel", 0, 10, 0.8),
+            token(b"lo", 10, 20, 0.6),
+            token(b",", 20, 22, 0.9),
+            token(b" world", 30, 60, 1.0),
+        ];
+        let words = group_tokens_into_words(&tokens);
+
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].text, "Hello,");
+        assert_eq!((words[0].start, words[0].end), (0, 22));
+        assert!((words[0].probability - 0.766).abs() < 0.01);
+        assert_eq!(words[1].text, "world");
+    }
+
+    #[test]
+    fn test_group_split_utf8_and_cjk() {
+        // "日本" where "本" (E6 9C AC) is split across two tokens
+        let tokens = vec![
+            token("日".as_bytes(), 0, 10, 1.0),
+            token(&[0xE6, 0x9C], 10, 15, 1.0),
+            token(&[0xAC], 15, 20, 1.0),
+        ];
+        let words = group_tokens_into_words(&tokens);
+
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].text, "日");
+        assert_eq!(words[1].text, "本");
+        assert_eq!((words[1].start, words[1].end), (10, 20));
+    }
+
+    #[test]
+    fn test_offset_segments() {
+        let mut segments = vec![TranscriptSegment {
+            start: 10,
+            end: 20,
+            words: vec![TranscriptWord {
+                start: 12,
+                end: 18,
+                text: "hi".to_string(),
+                probability: 1.0,
+            }],
+            ..Default::default()
+        }];
+        offset_segments(&mut segments, 240000);
+
+        assert_eq!((segments[0].start, segments[0].end), (240010, 240020));
+        assert_eq!(
+            (segments[0].words[0].start, segments[0].words[0].end),
+            (240012, 240018)
+        );
+    }
 }
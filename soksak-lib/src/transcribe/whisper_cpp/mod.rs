@@ -1,18 +1,28 @@
 use std::{
     ffi::{c_int, c_void},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{Result, anyhow};
 
 use std::io::Write;
 use tempfile::NamedTempFile;
-use whisper_rs::{FullParams, WhisperContext, WhisperContextParameters, WhisperVadParams};
+use whisper_rs::{
+    FullParams, WhisperContext, WhisperContextParameters, WhisperState, WhisperVadContext,
+    WhisperVadContextParams, WhisperVadParams,
+};
 
 use crate::{
-    config::{Language, TranscriptionConfig, WhisperConfig},
-    ffmpeg_decoder,
-    transcribe::TranscriptSegment,
+    checkpoint::CheckpointOptions,
+    config::{ChunkConfig, Language, TranscriptionConfig, WhisperConfig},
+    ffmpeg_decoder::{self, AudioSelection, AudioSource, SAMPLE_RATE, TimeRange},
+    output,
+    transcribe::{
+        SegmentQuality, TimedToken, TranscriptSegment, TranscriptWord,
+        checkpoint::{ChunkedSettings, InputFingerprint, TranscriptionCheckpoint},
+        chunk::{carry_over_prompt, find_cut, stitch_segments},
+        group_tokens_into_words, hallucination, offset_segments,
+    },
 };
 
 unsafe extern "C" fn whisper_progress_callback(
@@ -32,10 +42,13 @@ unsafe extern "C" fn whisper_progress_callback(
 pub struct Whisper {
     ctx: WhisperContext,
     lang: Language,
+    model_path: PathBuf,
 }
 
 const DEFAULT_BEAM_SIZE: u32 = 5;
 const DEFAULT_PATIENCE: f32 = 1.0;
+const DEFAULT_CHUNK_LENGTH: u32 = 600; // seconds
+const DEFAULT_CHUNK_OVERLAP: f32 = 2.0; // seconds
 
 impl Whisper {
     pub async fn new(conf: &TranscriptionConfig, lang: Language) -> Result<Self> {
@@ -54,28 +67,215 @@ impl Whisper {
         let param = WhisperContextParameters::default();
         let ctx = WhisperContext::new_with_params(model_path.to_str().unwrap(), param)?;
 
-        Ok(Self { ctx, lang })
+        Ok(Self {
+            ctx,
+            lang,
+            model_path,
+        })
     }
 
     pub fn transcribe<P: AsRef<Path>>(
         &mut self,
         audio: P,
         conf: &WhisperConfig,
+        range: &TimeRange,
+        checkpoint: Option<&CheckpointOptions>,
+        pb: &mut indicatif::ProgressBar,
+    ) -> Result<Vec<TranscriptSegment>> {
+        let chunk = conf.chunk.clone().unwrap_or_default();
+        let chunked = chunk.enabled.unwrap_or(true);
+
+        // Keep the temp file alive as long as params is used
+        let vad_model = if conf.vad.unwrap_or(true) || chunked {
+            Some(vad_model_file()?)
+        } else {
+            None
+        };
+        let vad_model_path = match &vad_model {
+            Some(file) => Some(file.path().to_str().ok_or(anyhow!("Invalid path"))?),
+            None => None,
+        };
+
+        let segments = if chunked {
+            self.transcribe_chunked(audio, conf, &chunk, range, checkpoint, vad_model_path, pb)?
+        } else {
+            // Checkpoints are written per chunk
+            if checkpoint.is_some_and(|c| c.resume) {
+                println!(
+                    "Chunking is disabled, so there is no checkpoint; starting from the beginning"
+                );
+            }
+            let audio =
+                ffmpeg_decoder::read_file(audio, range, &AudioSelection::from_config(conf))?;
+
+            let mut params = self.params(conf, conf.initial_prompt.as_deref(), vad_model_path)?;
+
+            // Set the progress callback to update the provided ProgressBar
+            #[allow(clippy::missing_transmute_annotations)]
+            unsafe {
+                params.set_progress_callback(Some(std::mem::transmute(
+                    whisper_progress_callback as *const (),
+                )));
+                params.set_progress_callback_user_data(pb as *mut _ as *mut c_void);
+            }
+
+            let mut state = self.ctx.create_state()?;
+            state.full(params, &audio)?;
+
+            let mut segments = self.collect_segments(&state, conf)?;
+            offset_segments(&mut segments, range.offset());
+            segments
+        };
+
+        if segments.is_empty() {
+            return Err(anyhow!("no segments found"));
+        }
+
+        Ok(segments)
+    }
+
+    /// Transcribes the input one window at a time: each window is decoded on
+    /// its own, cut at the latest silence VAD finds in its second half, and
+    /// transcribed with the tail of the previous window as prompt.
+    #[allow(clippy::too_many_arguments)]
+    fn transcribe_chunked<P: AsRef<Path>>(
+        &mut self,
+        audio: P,
+        conf: &WhisperConfig,
+        chunk: &ChunkConfig,
+        range: &TimeRange,
+        checkpoint: Option<&CheckpointOptions>,
+        vad_model_path: Option<&str>,
         pb: &mut indicatif::ProgressBar,
     ) -> Result<Vec<TranscriptSegment>> {
+        let length = chunk.length.unwrap_or(DEFAULT_CHUNK_LENGTH).max(30) as i64 * 100;
+        let overlap =
+            ((chunk.overlap.unwrap_or(DEFAULT_CHUNK_OVERLAP) * 100.0) as i64).clamp(0, length / 2);
+
+        let source = AudioSource::open(&audio, &AudioSelection::from_config(conf))?;
+        let range_start = range.offset();
+        let range_end = match (range.end, source.duration()) {
+            (Some(end), Some(duration)) => Some(end.min(duration)),
+            (end, duration) => end.or(duration),
+        };
+
+        let mut vad = WhisperVadContext::new(
+            vad_model_path.ok_or(anyhow!("VAD model is required for chunking"))?,
+            WhisperVadContextParams::new(),
+        )?;
+        let mut state = self.ctx.create_state()?;
+        let mut segments = Vec::new();
+        let mut position = range_start;
+
+        let settings = match checkpoint {
+            Some(_) => Some(ChunkedSettings {
+                input: InputFingerprint::of(audio.as_ref())?,
+                model: self.model_path.clone(),
+                lang: self.lang,
+                audio_stream: conf.audio_stream.clone(),
+                audio_channel: conf.audio_channel.unwrap_or_default(),
+                range_start: range.start,
+                range_end: range.end,
+                chunk_length: length,
+                chunk_overlap: overlap,
+            }),
+            None => None,
+        };
+        if let (Some(options), Some(settings)) = (checkpoint, &settings) {
+            if options.resume {
+                match crate::checkpoint::load::<TranscriptionCheckpoint>(&options.path)? {
+                    Some(saved) => {
+                        saved.validate(settings)?;
+                        println!(
+                            "Resuming from {} ({} segments already transcribed)",
+                            output::format_timestamp(saved.position),
+                            saved.segments.len()
+                        );
+                        position = saved.position;
+                        segments = saved.segments;
+                    }
+                    None => println!("No checkpoint found, starting from the beginning"),
+                }
+            }
+        }
+
+        loop {
+            let window_end = match range_end {
+                Some(end) => (position + length).min(end),
+                None => position + length,
+            };
+            if window_end <= position {
+                break;
+            }
+
+            let samples = source.read(&TimeRange::new(Some(position), Some(window_end))?)?;
+            let window_len = (samples.len() * 100 / SAMPLE_RATE) as i64;
+            if window_len == 0 {
+                break;
+            }
+            // A window well short of what was asked for hit the end of the input
+            let is_last = range_end == Some(window_end) || window_len + 100 < window_end - position;
+
+            let (cut, next) = if is_last {
+                (window_len, None)
+            } else {
+                let speech: Vec<(i64, i64)> = vad
+                    .segments_from_samples(chunk_vad_params(), &samples)?
+                    .map(|s| (s.start as i64, s.end as i64))
+                    .collect();
+                match find_cut(&speech, window_len, window_len / 2) {
+                    Some(cut) => (cut, Some(position + cut)),
+                    // Nowhere quiet to cut, so overlap the next window and de-duplicate
+                    None => (window_len, Some(position + window_len - overlap)),
+                }
+            };
+
+            let prompt = carry_over_prompt(conf.initial_prompt.as_deref(), &segments);
+            let params = self.params(conf, prompt.as_deref(), vad_model_path)?;
+            let cut_samples = (cut as usize * SAMPLE_RATE / 100).min(samples.len());
+            state.full(params, &samples[..cut_samples])?;
+
+            let mut chunk_segments = self.collect_segments(&state, conf)?;
+            offset_segments(&mut chunk_segments, position);
+            stitch_segments(&mut segments, chunk_segments);
+
+            if let Some(total) = range_end {
+                let done = (position + cut - range_start) * 100 / (total - range_start).max(1);
+                pb.set_position(done.clamp(0, 100) as u64);
+            }
+
+            let Some(next) = next else {
+                break;
+            };
+            position = next;
+
+            if let (Some(options), Some(settings)) = (checkpoint, &settings) {
+                crate::checkpoint::save(
+                    &TranscriptionCheckpoint {
+                        settings: settings.clone(),
+                        position,
+                        segments: segments.clone(),
+                    },
+                    &options.path,
+                )?;
+            }
+        }
+
+        Ok(segments)
+    }
+
+    fn params<'a>(
+        &self,
+        conf: &WhisperConfig,
+        prompt: Option<&str>,
+        vad_model_path: Option<&str>,
+    ) -> Result<FullParams<'a, 'a>> {
         // make parameters
         let mut params = FullParams::new(whisper_rs::SamplingStrategy::BeamSearch {
             beam_size: conf.beam_size.unwrap_or(DEFAULT_BEAM_SIZE) as c_int,
             patience: conf.patience.unwrap_or(DEFAULT_PATIENCE),
         });
 
-        // Silero VAD model (MIT License)
-        // Copyright (c) 2021 Silero Team
-        const SILERO_MODEL: &[u8] = include_bytes!("models/silero_vad.bin");
-
-        // Keep the temp file alive as long as params is used
-        let _vad_temp_file;
-
         if conf.vad.unwrap_or(true) {
             let mut vod_params = WhisperVadParams::new();
             vod_params.set_min_speech_duration(150);
@@ -84,19 +284,8 @@ impl Whisper {
             params.set_no_context(true);
 
             params.set_vad_params(vod_params);
-
-            // Write embedded model to a temporary file using tempfile crate
-            let mut temp_file = NamedTempFile::new()?;
-            temp_file.write_all(SILERO_MODEL)?;
-
-            params.set_vad_model_path(Some(
-                temp_file.path().to_str().ok_or(anyhow!("Invalid path"))?,
-            ));
+            params.set_vad_model_path(vad_model_path);
             params.enable_vad(true);
-
-            _vad_temp_file = Some(temp_file);
-        } else {
-            _vad_temp_file = None;
         }
 
         // Disable progress printing
@@ -105,47 +294,125 @@ impl Whisper {
         params.set_print_realtime(false);
         params.set_print_timestamps(false);
 
-        // Disable token timestamps
-        params.set_token_timestamps(false);
+        // Token timestamps are only needed for word-level timing
+        params.set_token_timestamps(conf.word_timestamps.unwrap_or(false));
 
         // Set temperature
         params.set_temperature(conf.temperature.unwrap_or(0.0));
 
         params.set_language(Some(self.lang.as_str()));
-        match conf.initial_prompt.as_ref() {
-            Some(prompt) => params.set_initial_prompt(prompt),
-            None => {}
-        }
-
-        // Set the progress callback to update the provided ProgressBar
-        unsafe {
-            params.set_progress_callback(Some(std::mem::transmute(
-                whisper_progress_callback as *const (),
-            )));
-            params.set_progress_callback_user_data(pb as *mut _ as *mut c_void);
+        if let Some(prompt) = prompt {
+            params.set_initial_prompt(prompt);
         }
 
-        let audio = ffmpeg_decoder::read_file(audio)?;
-
-        let mut state = self.ctx.create_state()?;
-        state.full(params, &audio)?;
-
-        let num_segments = state.full_n_segments();
-        if num_segments < 1 {
-            return Err(anyhow!("no segments found"));
-        }
+        Ok(params)
+    }
 
-        let mut words = Vec::with_capacity(num_segments as usize);
+    /// Reads the segments of the last `full` run, with timestamps relative
+    /// to the audio it was given.
+    fn collect_segments(
+        &self,
+        state: &WhisperState,
+        conf: &WhisperConfig,
+    ) -> Result<Vec<TranscriptSegment>> {
+        let word_timestamps = conf.word_timestamps.unwrap_or(false);
+        let use_vad = conf.vad.unwrap_or(true);
+        let token_eot = self.ctx.token_eot();
+        let mut segments = Vec::with_capacity(state.full_n_segments().max(0) as usize);
 
         for segment in state.as_iter() {
             let text = segment.to_str_lossy()?.to_string();
             let start = segment.start_timestamp();
             let end = segment.end_timestamp();
 
-            words.push(TranscriptSegment { start, end, text });
+            let mut tokens = Vec::with_capacity(segment.n_tokens() as usize);
+            let mut logprob_sum = 0.0;
+            for i in 0..segment.n_tokens() {
+                let Some(token) = segment.get_token(i) else {
+                    continue;
+                };
+                // Skip special tokens ([_BEG_], timestamps, ...)
+                if token.token_id() >= token_eot {
+                    continue;
+                }
+                let data = token.token_data();
+                logprob_sum += data.plog;
+                tokens.push(TimedToken {
+                    bytes: token.to_bytes()?.to_vec(),
+                    start: data.t0,
+                    end: data.t1,
+                    probability: data.p,
+                });
+            }
+
+            let quality = SegmentQuality {
+                avg_logprob: logprob_sum / tokens.len().max(1) as f32,
+                no_speech_prob: segment.no_speech_probability(),
+                compression_ratio: hallucination::compression_ratio(text.trim()),
+            };
+
+            let words = if word_timestamps {
+                let mut words = group_tokens_into_words(&tokens);
+                if use_vad {
+                    // whisper.cpp maps segment timestamps back to the original
+                    // timeline after VAD, but not token timestamps
+                    rescale_words(&mut words, start, end);
+                }
+                words
+            } else {
+                Vec::new()
+            };
+
+            segments.push(TranscriptSegment {
+                start,
+                end,
+                text,
+                words,
+                quality: Some(quality),
+                flagged: None,
+            });
         }
 
-        Ok(words)
+        Ok(segments)
+    }
+}
+
+/// Writes the embedded Silero VAD model to a temporary file for whisper.cpp.
+fn vad_model_file() -> Result<NamedTempFile> {
+    // Silero VAD model (MIT License)
+    // Copyright (c) 2021 Silero Team
+    const SILERO_MODEL: &[u8] = include_bytes!("models/silero_vad.bin");
+
+    // Write embedded model to a temporary file using tempfile crate
+    let mut temp_file = NamedTempFile::new()?;
+    temp_file.write_all(SILERO_MODEL)?;
+    Ok(temp_file)
+}
+
+/// VAD settings for finding chunk boundaries: only pauses long enough to be
+/// sentence breaks count.
+fn chunk_vad_params() -> WhisperVadParams {
+    let mut params = WhisperVadParams::new();
+    params.set_min_speech_duration(250);
+    params.set_min_silence_duration(300);
+    params.set_speech_pad(0);
+    params
+}
+
+/// Linearly maps word timestamps onto the segment's `start..end` span.
+fn rescale_words(words: &mut [TranscriptWord], start: i64, end: i64) {
+    let (Some(first), Some(last)) = (words.first(), words.last()) else {
+        return;
+    };
+    let (raw_start, raw_end) = (first.start, last.end);
+    if raw_end <= raw_start {
+        return;
+    }
+
+    let map = |t: i64| start + (t - raw_start) * (end - start) / (raw_end - raw_start);
+    for word in words.iter_mut() {
+        word.start = map(word.start);
+        word.end = map(word.end);
     }
 }
 
@@ -1,6 +1,6 @@
-// use crate::ffmpeg_decoder;
 use crate::config::WhisperConfig;
-use crate::transcribe::TranscriptSegment;
+use crate::ffmpeg_decoder::{self, AudioSelection, TimeRange};
+use crate::transcribe::{TranscriptSegment, offset_segments};
 use anyhow::{Result, anyhow};
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_void};
@@ -42,6 +42,7 @@ extern "C" fn whisperkit_callback(
                 start: (start * 100.0) as i64, // s to cs
                 end: (end * 100.0) as i64,     // s to cs
                 text: text_str,
+                ..Default::default()
             };
             let _ = sender.send(BridgeMessage::Segment(segment));
         } else {
@@ -123,11 +124,21 @@ impl WhisperKit {
         &self,
         audio: P,
         conf: &WhisperConfig,
+        range: &TimeRange,
         pb: &mut indicatif::ProgressBar,
     ) -> Result<Vec<TranscriptSegment>> {
-        // let audio = ffmpeg_decoder::file(audio)?;
-        let audio_path = audio
+        // WhisperKit reads the file itself, so cut out the range and the
+        // selected audio beforehand
+        let selection = AudioSelection::from_config(conf);
+        let clip = if range.is_full() && selection.is_default() {
+            None
+        } else {
+            Some(ffmpeg_decoder::file(&audio, range, &selection)?)
+        };
+        let audio_path = clip
             .as_ref()
+            .map(|f| f.path())
+            .unwrap_or(audio.as_ref())
             .to_str()
             .ok_or_else(|| anyhow!("Invalid audio path"))?;
 
@@ -181,6 +192,8 @@ impl WhisperKit {
             let _ = Box::from_raw(tx_ptr);
         }
 
+        offset_segments(&mut segments, range.offset());
+
         Ok(segments)
     }
 }
@@ -210,7 +223,7 @@ mod tests {
         // Try with local model first
         let whisper = WhisperKit::new_with_model_name(model_path, None);
         let conf = WhisperConfig::default();
-        let result = whisper.transcribe(&audio_path, &conf, &mut pb);
+        let result = whisper.transcribe(&audio_path, &conf, &TimeRange::default(), &mut pb);
 
         match result {
             Ok(segments) => {
@@ -225,7 +238,8 @@ mod tests {
                 println!("Attempting to download and use 'openai_whisper-tiny'...");
 
                 let whisper_tiny = WhisperKit::new_with_model_name("openai_whisper-tiny", None);
-                let result_tiny = whisper_tiny.transcribe(&audio_path, &conf, &mut pb);
+                let result_tiny =
+                    whisper_tiny.transcribe(&audio_path, &conf, &TimeRange::default(), &mut pb);
 
                 match result_tiny {
                     Ok(segments) => {
@@ -0,0 +1,359 @@
+use crate::checkpoint;
+use crate::config::{AppConfig, Language, Translate, TranslateEngine};
+use crate::llm::{LlmClient, Message};
+use crate::transcribe::TranscriptSegment;
+use crate::translate::checkpoint::source_crc32;
+use anyhow::{Context, Result};
+use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// What a translator should know about the whole transcript before reading
+/// any part of it. One brief serves every target language.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranslationBrief {
+    pub target_langs: Vec<Language>,
+    pub source_crc32: u32, // of the transcript it was written for
+    pub topic: String,
+    pub tone: String,
+    pub speakers: Vec<Speaker>,
+    pub key_terms: Vec<KeyTerm>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Speaker {
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyTerm {
+    pub source: String,
+    pub translations: BTreeMap<Language, String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub note: String,
+}
+
+/// The brief as the model returns it, before the target languages are attached.
+#[derive(Debug, Deserialize)]
+struct BriefResponse {
+    topic: String,
+    tone: String,
+    speakers: Vec<Speaker>,
+    key_terms: Vec<KeyTerm>,
+}
+
+fn brief_schema(target_langs: &[Language]) -> serde_json::Value {
+    let langs: Vec<String> = target_langs.iter().map(|l| l.to_string()).collect();
+    let lang_properties: serde_json::Map<String, serde_json::Value> = langs
+        .iter()
+        .map(|l| (l.clone(), serde_json::json!({ "type": "string" })))
+        .collect();
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "topic": { "type": "string" },
+            "tone": { "type": "string" },
+            "speakers": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "description": { "type": "string" }
+                    },
+                    "required": ["name", "description"],
+                    "additionalProperties": false
+                }
+            },
+            "key_terms": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "source": { "type": "string" },
+                        "translations": {
+                            "type": "object",
+                            "properties": lang_properties,
+                            "required": langs,
+                            "additionalProperties": false
+                        },
+                        "note": { "type": "string" }
+                    },
+                    "required": ["source", "translations", "note"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["topic", "tone", "speakers", "key_terms"],
+        "additionalProperties": false
+    })
+}
+
+impl TranslationBrief {
+    /// Renders the brief as a section of a system prompt for translating
+    /// into `lang`.
+    pub fn to_prompt(&self, lang: Language) -> String {
+        let mut prompt = format!(
+            "Translation brief for the whole video:\nTopic: {}\nTone: {}\n",
+            self.topic, self.tone
+        );
+        if !self.speakers.is_empty() {
+            prompt.push_str("Speakers:\n");
+            for speaker in &self.speakers {
+                prompt.push_str(&format!("- {}: {}\n", speaker.name, speaker.description));
+            }
+        }
+        if !self.key_terms.is_empty() {
+            prompt.push_str("Key terms (use these translations consistently):\n");
+            for term in &self.key_terms {
+                let Some(translation) = term.translations.get(&lang) else {
+                    continue;
+                };
+                if term.note.is_empty() {
+                    prompt.push_str(&format!("- {} => {}\n", term.source, translation));
+                } else {
+                    prompt.push_str(&format!(
+                        "- {} => {} ({})\n",
+                        term.source, translation, term.note
+                    ));
+                }
+            }
+        }
+        prompt.push('\n');
+        prompt
+    }
+}
+
+/// Writes the brief for `translate.brief`, or returns `None` if it isn't
+/// configured. The brief at `path` is reused if it was written for the same
+/// transcript and covers all of `target_langs`, so it can be reviewed and
+/// edited between runs; otherwise the whole transcript is read once to write
+/// a new one.
+pub async fn prepare(
+    translate_config: &Translate,
+    target_langs: &[Language],
+    app_config: &AppConfig,
+    segments: &[TranscriptSegment],
+    path: Option<&Path>,
+    pb: &ProgressBar,
+) -> Result<Option<TranslationBrief>> {
+    let Some(brief_config) = &translate_config.brief else {
+        return Ok(None);
+    };
+
+    if let Some(path) = path {
+        match checkpoint::load::<TranslationBrief>(path) {
+            // Edits to the brief are lost, so say why it is replaced
+            Ok(Some(brief)) if brief.source_crc32 != source_crc32(segments) => pb.println(format!(
+                "Warning: translation brief {:?} was written for a different transcript, replacing it with a new one",
+                path
+            )),
+            Ok(Some(brief)) if target_langs.iter().all(|l| brief.target_langs.contains(l)) => {
+                pb.println(format!("Using translation brief {:?}", path));
+                return Ok(Some(brief));
+            }
+            Ok(Some(_)) => pb.println(format!(
+                "Translation brief {:?} doesn't cover all target languages, writing a new one",
+                path
+            )),
+            Ok(None) => {}
+            // Hand-edited into invalid JSON, or written by an older version
+            Err(e) => pb.println(format!("{:#}, writing a new one", e)),
+        }
+    }
+
+    let model = match (&brief_config.model, &translate_config.engine) {
+        (Some(model), _) => model,
+        (None, TranslateEngine::LLM { model, .. }) => model,
+        #[cfg(feature = "apple")]
+        (None, TranslateEngine::Apple { .. }) => {
+            anyhow::bail!("translate.brief.model is required with the Apple engine")
+        }
+    };
+    let (provider_id, model_name) = model.split_once('/').unwrap_or((model, "default"));
+    let provider_config = app_config
+        .llm
+        .providers
+        .iter()
+        .find(|p| p.id == provider_id)
+        .ok_or_else(|| anyhow::anyhow!("Provider {} not found", provider_id))?;
+    let client = LlmClient::new(provider_config.clone())?;
+
+    pb.println("Writing translation brief...");
+    let brief = generate(&client, model_name, segments, target_langs).await?;
+    if let Some(path) = path {
+        checkpoint::save(&brief, path)?;
+        pb.println(format!("Saved translation brief to {:?}", path));
+    }
+    Ok(Some(brief))
+}
+
+async fn generate(
+    client: &LlmClient,
+    model_name: &str,
+    segments: &[TranscriptSegment],
+    target_langs: &[Language],
+) -> Result<TranslationBrief> {
+    let transcript = segments
+        .iter()
+        .map(|s| s.text.trim())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let langs = target_langs
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let system_prompt = format!(
+        "You prepare briefs for video subtitle translators. Read the whole transcript and describe:\n\
+        - topic: what the video is about, in one or two sentences\n\
+        - tone: the register and style the translation should keep (e.g. casual, formal, technical)\n\
+        - speakers: each speaker's name or role, and how they should be addressed\n\
+        - key_terms: names, jargon and recurring phrases, each with `translations` keyed by \
+        language code ({}) to use throughout, and an optional note\n\
+        Output ONLY a JSON object with the keys `topic`, `tone`, `speakers` and `key_terms`.",
+        langs
+    );
+
+    let messages = vec![
+        Message {
+            role: "system".to_string(),
+            content: system_prompt,
+        },
+        Message {
+            role: "user".to_string(),
+            content: format!("Transcript:\n{}", transcript),
+        },
+    ];
+
+    client.check_input(&messages).context(
+        "The transcript is too long to write the translation brief in one request; raise the \
+        provider's max_input_tokens if the model's context allows, translate a shorter \
+        --start/--end range, or remove translate.brief",
+    )?;
+    let response_text = client
+        .chat_completion(model_name, messages, true, Some(brief_schema(target_langs)))
+        .await?;
+    let response = parse_brief_response(&response_text)?;
+
+    Ok(TranslationBrief {
+        target_langs: target_langs.to_vec(),
+        source_crc32: source_crc32(segments),
+        topic: response.topic,
+        tone: response.tone,
+        speakers: response.speakers,
+        key_terms: response.key_terms,
+    })
+}
+
+fn parse_brief_response(response_text: &str) -> Result<BriefResponse> {
+    let clean_response = response_text
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    serde_json::from_str(clean_response)
+        .with_context(|| format!("Failed to parse translation brief: {}", response_text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_brief_prompt() {
+        let response = parse_brief_response(
+            "```json\n{\"topic\": \"A Rust meetup talk.\", \"tone\": \"casual\", \
+            \"speakers\": [{\"name\": \"Jiho\", \"description\": \"host\"}], \
+            \"key_terms\": [{\"source\": \"borrow checker\", \
+            \"translations\": {\"ko\": \"빌림 검사기\", \"ja\": \"借用チェッカー\"}, \"note\": \"\"}]}\n```",
+        )
+        .unwrap();
+        let brief = TranslationBrief {
+            target_langs: vec![Language::Korean, Language::Japanese],
+            source_crc32: 0,
+            topic: response.topic,
+            tone: response.tone,
+            speakers: response.speakers,
+            key_terms: response.key_terms,
+        };
+
+        assert_eq!(
+            brief.to_prompt(Language::Korean),
+            "Translation brief for the whole video:\n\
+            Topic: A Rust meetup talk.\n\
+            Tone: casual\n\
+            Speakers:\n\
+            - Jiho: host\n\
+            Key terms (use these translations consistently):\n\
+            - borrow checker => 빌림 검사기\n\n"
+        );
+        assert!(
+            brief
+                .to_prompt(Language::Japanese)
+                .contains("- borrow checker => 借用チェッカー\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prepare_replaces_brief_of_other_transcript() {
+        // No provider is configured, so writing a new brief fails
+        let app_config: AppConfig =
+            serde_yaml::from_str("transcription:\n  models: {}\nllm:\n  providers: []").unwrap();
+        let translate_config: Translate = serde_yaml::from_str(
+            "engine:\n  type: LLM\n  model: missing/model\ntarget_lang: ko\nbrief: {}",
+        )
+        .unwrap();
+        let segments = vec![TranscriptSegment {
+            start: 0,
+            end: 100,
+            text: "Hello".to_string(),
+            ..Default::default()
+        }];
+        let brief = TranslationBrief {
+            target_langs: vec![Language::Korean],
+            source_crc32: source_crc32(&segments),
+            topic: "A greeting.".to_string(),
+            tone: "casual".to_string(),
+            speakers: Vec::new(),
+            key_terms: Vec::new(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("talk.brief.json");
+        checkpoint::save(&brief, &path).unwrap();
+
+        let pb = ProgressBar::hidden();
+        let langs = [Language::Korean];
+        let reused = prepare(
+            &translate_config,
+            &langs,
+            &app_config,
+            &segments,
+            Some(&path),
+            &pb,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(reused.topic, "A greeting.");
+
+        let mut edited = segments;
+        edited[0].text = "Goodbye".to_string();
+        assert!(
+            prepare(
+                &translate_config,
+                &langs,
+                &app_config,
+                &edited,
+                Some(&path),
+                &pb
+            )
+            .await
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use crate::config::Language;
+use crate::transcribe::TranscriptSegment;
+use crate::translate::TranslatedSegment;
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// Translated windows so far and the rolling summary after the last of them,
+/// saved after every window.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranslationCheckpoint {
+    pub source_crc32: u32, // of the transcript segments being translated
+    pub engine: String,
+    pub target_lang: Language,
+    pub window_size: usize,
+    pub completed_windows: usize,
+    pub summary: String,
+    pub segments: Vec<TranslatedSegment>,
+    #[serde(default)]
+    pub untranslated: Vec<TranslatedSegment>,
+}
+
+/// CRC32 of the segments' timing and text, to tell whether a checkpoint
+/// belongs to the same transcript.
+pub fn source_crc32(segments: &[TranscriptSegment]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    for segment in segments {
+        crc.update(&segment.start.to_le_bytes());
+        crc.update(&segment.end.to_le_bytes());
+        crc.update(segment.text.as_bytes());
+    }
+    crc.sum()
+}
+
+impl TranslationCheckpoint {
+    /// Fails unless resuming would reproduce the same windows with the same engine.
+    pub fn validate(
+        &self,
+        source_crc32: u32,
+        engine: &str,
+        target_lang: &Language,
+        window_size: usize,
+    ) -> Result<()> {
+        if self.source_crc32 != source_crc32 {
+            bail!("Transcript has changed since the checkpoint was written; run without --resume");
+        }
+        if self.engine != engine || self.target_lang != *target_lang {
+            bail!(
+                "Checkpoint was written for {} into {}, not {} into {}; run without --resume",
+                self.engine,
+                self.target_lang,
+                engine,
+                target_lang
+            );
+        }
+        if self.window_size != window_size {
+            bail!(
+                "Checkpoint was written with window {}, not {}; run without --resume",
+                self.window_size,
+                window_size
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_validation() {
+        let mut segments = vec![TranscriptSegment {
+            start: 0,
+            end: 100,
+            text: "Hello".to_string(),
+            ..Default::default()
+        }];
+        let crc = source_crc32(&segments);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("talk.translation.checkpoint.json");
+        let saved = TranslationCheckpoint {
+            source_crc32: crc,
+            engine: "openai/gpt-4o".to_string(),
+            target_lang: Language::Korean,
+            window_size: 100,
+            completed_windows: 1,
+            summary: "A greeting.".to_string(),
+            segments: Vec::new(),
+            untranslated: Vec::new(),
+        };
+        crate::checkpoint::save(&saved, &path).unwrap();
+
+        let loaded: TranslationCheckpoint = crate::checkpoint::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.summary, "A greeting.");
+        assert!(
+            loaded
+                .validate(crc, "openai/gpt-4o", &Language::Korean, 100)
+                .is_ok()
+        );
+        assert!(
+            loaded
+                .validate(crc, "openai/gpt-4o", &Language::Korean, 50)
+                .is_err()
+        );
+        assert!(
+            loaded
+                .validate(crc, "openai/gpt-4o", &Language::Japanese, 100)
+                .is_err()
+        );
+
+        segments[0].text = "Hello!".to_string();
+        assert!(
+            loaded
+                .validate(
+                    source_crc32(&segments),
+                    "openai/gpt-4o",
+                    &Language::Korean,
+                    100
+                )
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::config::{GlossaryConfig, GlossaryEntry};
+use crate::translate::TranslatedSegment;
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+pub struct Glossary {
+    entries: Vec<GlossaryEntry>,
+}
+
+/// A segment whose source has a glossary term but whose translation lacks
+/// the required rendering of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlossaryViolation {
+    pub start: i64,
+    pub end: i64,
+    pub term: String,
+    pub expected: String,
+}
+
+impl Glossary {
+    pub fn load(config: &GlossaryConfig) -> Result<Self> {
+        let entries = match config {
+            GlossaryConfig::Entries(entries) => entries.clone(),
+            GlossaryConfig::Path(path) => read_table(path)?,
+        };
+        for entry in &entries {
+            if entry.source.trim().is_empty() {
+                bail!("Glossary entry with an empty source term");
+            }
+            if entry.target.is_none() && !entry.do_not_translate.unwrap_or(false) {
+                bail!(
+                    "Glossary entry {:?} needs a target or do_not_translate",
+                    entry.source
+                );
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[GlossaryEntry] {
+        &self.entries
+    }
+
+    /// Entries whose source term occurs in any of `texts`.
+    pub fn entries_in<'a>(&'a self, texts: &[&str]) -> Vec<&'a GlossaryEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                texts
+                    .iter()
+                    .any(|text| contains_term(text, &entry.source, is_case_sensitive(entry)))
+            })
+            .collect()
+    }
+
+    /// Finds segments where a term occurs in the original but its required
+    /// translation is missing from the translated text.
+    pub fn check(&self, segments: &[TranslatedSegment]) -> Vec<GlossaryViolation> {
+        let mut violations = Vec::new();
+        for segment in segments {
+            for entry in &self.entries {
+                let case_sensitive = is_case_sensitive(entry);
+                if !contains_term(&segment.original, &entry.source, case_sensitive) {
+                    continue;
+                }
+                let expected = expected_target(entry);
+                if !contains_term(&segment.translated, expected, case_sensitive) {
+                    violations.push(GlossaryViolation {
+                        start: segment.start,
+                        end: segment.end,
+                        term: entry.source.clone(),
+                        expected: expected.to_string(),
+                    });
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Renders glossary entries as a section of a system prompt.
+pub fn to_prompt(entries: &[&GlossaryEntry]) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+    let mut prompt = String::from("Glossary (always translate these terms as given):\n");
+    for entry in entries {
+        match &entry.target {
+            _ if entry.do_not_translate.unwrap_or(false) => prompt.push_str(&format!(
+                "- {} => keep as is, do not translate\n",
+                entry.source
+            )),
+            Some(target) => prompt.push_str(&format!("- {} => {}\n", entry.source, target)),
+            None => {}
+        }
+    }
+    prompt.push('\n');
+    prompt
+}
+
+fn is_case_sensitive(entry: &GlossaryEntry) -> bool {
+    entry.case_sensitive.unwrap_or(false)
+}
+
+fn expected_target(entry: &GlossaryEntry) -> &str {
+    if entry.do_not_translate.unwrap_or(false) {
+        &entry.source
+    } else {
+        entry.target.as_deref().unwrap_or(&entry.source)
+    }
+}
+
+/// Whether `term` occurs in `text`, not as part of a longer word. Word
+/// boundaries only apply where the term itself starts or ends with an ASCII
+/// letter or digit, so terms in scripts without spaces still match.
+fn contains_term(text: &str, term: &str, case_sensitive: bool) -> bool {
+    let (text, term) = if case_sensitive {
+        (text.to_string(), term.to_string())
+    } else {
+        (text.to_lowercase(), term.to_lowercase())
+    };
+    let term = term.trim();
+    if term.is_empty() {
+        return false;
+    }
+    let is_word = |c: char| c.is_alphanumeric() && c.is_ascii();
+    let word_start = term.chars().next().is_some_and(is_word);
+    let word_end = term.chars().last().is_some_and(is_word);
+
+    text.match_indices(term).any(|(i, _)| {
+        let joined_before = word_start && text[..i].chars().next_back().is_some_and(is_word);
+        let joined_after = word_end && text[i + term.len()..].chars().next().is_some_and(is_word);
+        !joined_before && !joined_after
+    })
+}
+
+/// Reads a glossary table with the columns `source, target, case_sensitive,
+/// do_not_translate`. Tab-separated for `.tsv` files, comma-separated
+/// otherwise; a header row starting with `source` is skipped.
+fn read_table(path: &Path) -> Result<Vec<GlossaryEntry>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read glossary {:?}", path))?;
+    let delimiter = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("tsv") => '\t',
+        _ => ',',
+    };
+
+    let mut entries = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_row(line, delimiter);
+        if i == 0 && fields[0].trim().eq_ignore_ascii_case("source") {
+            continue;
+        }
+        let field = |n: usize| fields.get(n).map(|f| f.trim()).filter(|f| !f.is_empty());
+        let flag = |n: usize| -> Result<Option<bool>> {
+            field(n)
+                .map(|f| match f.to_lowercase().as_str() {
+                    "true" | "yes" | "y" | "1" => Ok(true),
+                    "false" | "no" | "n" | "0" => Ok(false),
+                    _ => bail!(
+                        "{:?} line {}: expected true or false, not {:?}",
+                        path,
+                        i + 1,
+                        f
+                    ),
+                })
+                .transpose()
+        };
+        entries.push(GlossaryEntry {
+            source: field(0).unwrap_or_default().to_string(),
+            target: field(1).map(str::to_string),
+            case_sensitive: flag(2)?,
+            do_not_translate: flag(3)?,
+        });
+    }
+    Ok(entries)
+}
+
+/// Splits a CSV/TSV row, honouring double-quoted fields with `""` escapes.
+fn split_row(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(String::new()),
+            c => field.push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn segment(original: &str, translated: &str) -> TranslatedSegment {
+        TranslatedSegment {
+            start: 0,
+            end: 100,
+            original: original.to_string(),
+            translated: translated.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_read_csv_glossary() {
+        let mut file = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+        writeln!(file, "source,target,case_sensitive,do_not_translate").unwrap();
+        writeln!(file, "\"borrow checker\",\"빌림 검사기\",,").unwrap();
+        writeln!(file, "Soksak,,true,yes").unwrap();
+        let glossary = Glossary::load(&GlossaryConfig::Path(file.path().to_path_buf())).unwrap();
+
+        assert_eq!(glossary.entries.len(), 2);
+        assert_eq!(glossary.entries[0].target.as_deref(), Some("빌림 검사기"));
+        assert_eq!(glossary.entries[1].case_sensitive, Some(true));
+        assert_eq!(glossary.entries[1].do_not_translate, Some(true));
+    }
+
+    #[test]
+    fn test_entries_in_and_check() {
+        let glossary = Glossary::load(&GlossaryConfig::Entries(vec![
+            GlossaryEntry {
+                source: "borrow checker".to_string(),
+                target: Some("빌림 검사기".to_string()),
+                case_sensitive: None,
+                do_not_translate: None,
+            },
+            GlossaryEntry {
+                source: "AI".to_string(),
+                target: None,
+                case_sensitive: Some(true),
+                do_not_translate: Some(true),
+            },
+        ]))
+        .unwrap();
+
+        // "AI" is case sensitive and not part of "said"
+        let found = glossary.entries_in(&["The Borrow Checker said no."]);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            to_prompt(&found),
+            "Glossary (always translate these terms as given):\n- borrow checker => 빌림 검사기\n\n"
+        );
+
+        let violations = glossary.check(&[
+            segment("The borrow checker and AI.", "빌림 검사기와 AI."),
+            segment("Ask the AI.", "인공지능에게 물어보세요."),
+        ]);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].term, "AI");
+        assert_eq!(violations[0].expected, "AI");
+    }
+}
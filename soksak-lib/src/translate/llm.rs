@@ -1,38 +1,208 @@
+use crate::config::JsonModeType;
 use crate::llm::{LlmClient, Message};
+use crate::transcribe::TranscriptSegment;
 use crate::translate::{BatchItem, BatchTranslationResponse, TranslatedSegment};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-pub async fn translate_batch(
+/// Lines on each side of a re-requested line given as context.
+const REPAIR_CONTEXT_LINES: usize = 2;
+
+#[derive(Debug, serde::Deserialize)]
+struct TranslationsResponse {
+    translations: Vec<BatchTranslationResponse>,
+}
+
+fn translations_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "translations": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "integer" },
+                        "translated_text": { "type": "string" }
+                    },
+                    "required": ["id", "translated_text"],
+                    "additionalProperties": false
+                }
+            }
+        },
+        "required": ["translations"],
+        "additionalProperties": false
+    })
+}
+
+/// Translations of a window, in window order. Lines in `untranslated` kept
+/// their source text.
+pub struct WindowTranslation {
+    pub translations: Vec<BatchTranslationResponse>,
+    pub untranslated: Vec<usize>,
+}
+
+/// Translates a window, re-requesting the lines a response missed, repeated
+/// or left untranslated. A request that yields nothing usable is split in
+/// halves. Each line is requested at most `max_attempts` times.
+pub async fn translate_window(
     client: &LlmClient,
     model_name: &str,
     batch_items: &[BatchItem],
     target_lang: &str,
     prepending_system_prompt: &str,
     summary: &str,
-) -> Result<Vec<BatchTranslationResponse>> {
-    // Join all texts with ID prefixes
-    let batch_text = batch_items
+    max_attempts: u32,
+) -> Result<WindowTranslation> {
+    let mut translated: HashMap<usize, String> = HashMap::new();
+    let mut untranslated = Vec::new();
+    let mut queue = VecDeque::from([(batch_items.to_vec(), 1)]);
+
+    while let Some((group, attempt)) = queue.pop_front() {
+        let context = if attempt == 1 {
+            String::new()
+        } else {
+            repair_context(batch_items, &group, &translated)
+        };
+        let responses = translate_batch(
+            client,
+            model_name,
+            &group,
+            target_lang,
+            prepending_system_prompt,
+            summary,
+            &context,
+        )
+        .await?;
+
+        let (accepted, problems) = check_translations(&group, responses);
+        if !problems.is_empty() {
+            log::warn!(
+                "Translation attempt {} of {} lines: {}",
+                attempt,
+                group.len(),
+                problems.join(", ")
+            );
+        }
+
+        let pending: Vec<BatchItem> = group
+            .into_iter()
+            .filter(|item| !accepted.contains_key(&item.id))
+            .collect();
+        let progressed = !accepted.is_empty();
+        translated.extend(accepted);
+
+        if pending.is_empty() {
+            continue;
+        }
+        if attempt >= max_attempts {
+            untranslated.extend(pending.iter().map(|item| item.id));
+        } else if !progressed && pending.len() > 1 {
+            let (first, second) = pending.split_at(pending.len() / 2);
+            queue.push_back((first.to_vec(), attempt + 1));
+            queue.push_back((second.to_vec(), attempt + 1));
+        } else {
+            queue.push_back((pending, attempt + 1));
+        }
+    }
+    untranslated.sort_unstable();
+
+    let translations = batch_items
         .iter()
-        .map(|item| format!("[{}] {}", item.id, item.text))
-        .collect::<Vec<_>>()
-        .join("\n");
+        .map(|item| BatchTranslationResponse {
+            id: item.id,
+            translated_text: translated
+                .remove(&item.id)
+                .unwrap_or_else(|| item.text.clone()),
+        })
+        .collect();
+    Ok(WindowTranslation {
+        translations,
+        untranslated,
+    })
+}
 
-    let system_prompt = format!(
-        "{}You are a professional video subtitle translator. Translate the following text into {}.\n\
-        The input text is a list of sentences, each starting with an ID in brackets like `[0]`, `[1]`, etc.\n\
-        Translate each line one by one and output the translated text with the SAME ID prefix.\n\
-        Example Input:\n\
-        [0] Hello world\n\
-        [1] How are you?\n\
-        Example Output:\n\
-        [0] Bonjour le monde\n\
-        [1] Comment allez-vous ?\n\
-        \n\
-        Maintain the exact ID for each line.\n\
-        Use the provided summary to ensure natural flow and correct tone.\n\
-        Output ONLY the translated text with IDs, no other comments or explanations.",
-        prepending_system_prompt, target_lang
-    );
+/// Neighbouring lines of the window around a repair request, with their
+/// translations where known.
+fn repair_context(
+    batch_items: &[BatchItem],
+    group: &[BatchItem],
+    translated: &HashMap<usize, String>,
+) -> String {
+    let requested: HashSet<usize> = group.iter().map(|item| item.id).collect();
+    let mut context = Vec::new();
+    for (i, item) in batch_items.iter().enumerate() {
+        let end = (i + REPAIR_CONTEXT_LINES + 1).min(batch_items.len());
+        let near = batch_items[i.saturating_sub(REPAIR_CONTEXT_LINES)..end]
+            .iter()
+            .any(|other| requested.contains(&other.id));
+        if !near || requested.contains(&item.id) {
+            continue;
+        }
+        match translated.get(&item.id) {
+            Some(translation) => context.push(format!("{} => {}", item.text, translation)),
+            None => context.push(item.text.clone()),
+        }
+    }
+    context.join("\n")
+}
+
+async fn translate_batch(
+    client: &LlmClient,
+    model_name: &str,
+    batch_items: &[BatchItem],
+    target_lang: &str,
+    prepending_system_prompt: &str,
+    summary: &str,
+    context: &str,
+) -> Result<Vec<BatchTranslationResponse>> {
+    // Providers without a JSON mode get the line format, which is easier to
+    // follow from the prompt alone
+    let json_mode = !matches!(client.json_mode_type(), JsonModeType::None);
+
+    let (system_prompt, batch_text) = if json_mode {
+        let system_prompt = format!(
+            "{}You are a professional video subtitle translator. Translate the following text into {}.\n\
+            The input is a JSON array of objects with an `id` and a `text`.\n\
+            Translate each text one by one and output a JSON object with a `translations` array \
+            containing one object per input, with the SAME `id` and the translation as `translated_text`.\n\
+            Example Input:\n\
+            [{{\"id\": 0, \"text\": \"Hello world\"}}, {{\"id\": 1, \"text\": \"How are you?\"}}]\n\
+            Example Output:\n\
+            {{\"translations\": [{{\"id\": 0, \"translated_text\": \"Bonjour le monde\"}}, {{\"id\": 1, \"translated_text\": \"Comment allez-vous ?\"}}]}}\n\
+            \n\
+            Return every id exactly once. Do not merge, split, or skip items.\n\
+            Use the provided summary to ensure natural flow and correct tone.\n\
+            Output ONLY the JSON.",
+            prepending_system_prompt, target_lang
+        );
+        (system_prompt, serde_json::to_string(batch_items)?)
+    } else {
+        let system_prompt = format!(
+            "{}You are a professional video subtitle translator. Translate the following text into {}.\n\
+            The input text is a list of sentences, each starting with an ID in brackets like `[0]`, `[1]`, etc.\n\
+            Translate each line one by one and output the translated text with the SAME ID prefix.\n\
+            Example Input:\n\
+            [0] Hello world\n\
+            [1] How are you?\n\
+            Example Output:\n\
+            [0] Bonjour le monde\n\
+            [1] Comment allez-vous ?\n\
+            \n\
+            Maintain the exact ID for each line.\n\
+            Use the provided summary to ensure natural flow and correct tone.\n\
+            Output ONLY the translated text with IDs, no other comments or explanations.",
+            prepending_system_prompt, target_lang
+        );
+        // Join all texts with ID prefixes
+        let batch_text = batch_items
+            .iter()
+            .map(|item| format!("[{}] {}", item.id, item.text))
+            .collect::<Vec<_>>()
+            .join("\n");
+        (system_prompt, batch_text)
+    };
 
     let messages = vec![
         Message {
@@ -41,25 +211,66 @@ pub async fn translate_batch(
         },
         Message {
             role: "user".to_string(),
-            content: format!(
-                "Summary of previous conversation: {}\n\nInput Text:\n{}",
-                summary, batch_text
-            ),
+            content: if context.is_empty() {
+                format!(
+                    "Summary of previous conversation: {}\n\nInput Text:\n{}",
+                    summary, batch_text
+                )
+            } else {
+                format!(
+                    "Summary of previous conversation: {}\n\n\
+                    Surrounding lines, for context only (do not include them in the output):\n{}\n\n\
+                    Input Text:\n{}",
+                    summary, context, batch_text
+                )
+            },
         },
     ];
 
-    let response_text = client
-        .chat_completion(model_name, messages, false, None)
-        .await?;
+    let response_text = if json_mode {
+        client
+            .chat_completion(model_name, messages, true, Some(translations_schema()))
+            .await?
+    } else {
+        client
+            .chat_completion(model_name, messages, false, None)
+            .await?
+    };
+
+    if !json_mode {
+        return Ok(parse_line_response(&response_text));
+    }
+    // An unparsable response is as good as an empty one; the lines get repaired
+    match parse_json_response(&response_text) {
+        Ok(responses) => Ok(responses),
+        Err(e) => {
+            log::warn!("{:#}", e);
+            Ok(Vec::new())
+        }
+    }
+}
+
+fn parse_json_response(response_text: &str) -> Result<Vec<BatchTranslationResponse>> {
+    let clean_response = response_text
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
 
+    let parsed: TranslationsResponse = serde_json::from_str(clean_response)
+        .with_context(|| format!("Failed to parse translation response: {}", response_text))?;
+    Ok(parsed.translations)
+}
+
+fn parse_line_response(response_text: &str) -> Vec<BatchTranslationResponse> {
     let clean_response = response_text
         .trim()
         .trim_start_matches("```")
         .trim_end_matches("```")
         .trim();
 
-    // Parse response into a map for easy lookup
-    let mut translated_map = std::collections::HashMap::new();
+    let mut responses = Vec::new();
     for line in clean_response.lines() {
         let line = line.trim();
         if line.is_empty() {
@@ -71,28 +282,123 @@ pub async fn translate_batch(
             if let Some(end_bracket) = line[start_bracket..].find(']') {
                 let end_bracket_idx = start_bracket + end_bracket;
                 if let Ok(id) = line[start_bracket + 1..end_bracket_idx].parse::<usize>() {
-                    let text = line[end_bracket_idx + 1..].trim().to_string();
-                    translated_map.insert(id, text);
+                    responses.push(BatchTranslationResponse {
+                        id,
+                        translated_text: line[end_bracket_idx + 1..].trim().to_string(),
+                    });
                 }
             }
         }
     }
+    responses
+}
 
-    // Map back to BatchTranslationResponse
-    let mut responses = Vec::new();
+/// Keeps the translations that answer exactly one requested id with
+/// something other than the source text, and describes what was wrong with
+/// the rest.
+fn check_translations(
+    batch_items: &[BatchItem],
+    responses: Vec<BatchTranslationResponse>,
+) -> (HashMap<usize, String>, Vec<String>) {
+    let sources: HashMap<usize, &str> = batch_items
+        .iter()
+        .map(|item| (item.id, item.text.as_str()))
+        .collect();
+    let mut translated: HashMap<usize, String> = HashMap::new();
+    let mut duplicate = Vec::new();
+    let mut extra = Vec::new();
+    for response in responses {
+        if !sources.contains_key(&response.id) {
+            extra.push(response.id);
+            continue;
+        }
+        match translated.entry(response.id) {
+            Entry::Occupied(_) => duplicate.push(response.id),
+            Entry::Vacant(entry) => {
+                entry.insert(response.translated_text);
+            }
+        }
+    }
+    // An id answered twice is ambiguous, so neither answer is kept
+    for id in &duplicate {
+        translated.remove(id);
+    }
+
+    let mut missing = Vec::new();
+    let mut unchanged = Vec::new();
     for item in batch_items {
-        let translated_text = translated_map.remove(&item.id).unwrap_or_else(|| {
-            // Fallback if ID not found
-            String::new()
-        });
+        match translated.get(&item.id) {
+            None if !duplicate.contains(&item.id) => missing.push(item.id),
+            Some(text) if is_untranslated(&item.text, text) => unchanged.push(item.id),
+            _ => {}
+        }
+    }
+    for id in &unchanged {
+        translated.remove(id);
+    }
 
-        responses.push(BatchTranslationResponse {
-            id: item.id,
-            translated_text,
-        });
+    let mut problems = Vec::new();
+    if !missing.is_empty() {
+        problems.push(format!("missing ids {:?}", missing));
+    }
+    if !extra.is_empty() {
+        problems.push(format!("unknown ids {:?}", extra));
+    }
+    if !duplicate.is_empty() {
+        problems.push(format!("duplicate ids {:?}", duplicate));
+    }
+    if !unchanged.is_empty() {
+        problems.push(format!("untranslated ids {:?}", unchanged));
     }
+    (translated, problems)
+}
 
-    Ok(responses)
+/// Whether a translation is empty or just repeats the source. Lines without
+/// letters, such as numbers or music notes, are fine to leave as they are.
+fn is_untranslated(source: &str, translated: &str) -> bool {
+    let translated = translated.trim();
+    translated.is_empty()
+        || (source.chars().any(char::is_alphabetic)
+            && source.trim().to_lowercase() == translated.to_lowercase())
+}
+
+/// Summarizes the whole transcript up front, for windows translated in
+/// parallel that can't wait for the rolling summary.
+pub async fn summarize_transcript(
+    client: &LlmClient,
+    model_name: &str,
+    segments: &[TranscriptSegment],
+) -> Result<String> {
+    let transcript = segments
+        .iter()
+        .map(|s| s.text.trim())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let summary_prompt = format!(
+        "Summarize the following transcript for a translator who will only see parts of it.\n\
+        Mention the topic, who is speaking and the tone.\n\
+        Transcript:\n{}\n\
+        Output ONLY the summary in three to five sentences.",
+        transcript
+    );
+
+    let messages = vec![Message {
+        role: "user".to_string(),
+        content: summary_prompt,
+    }];
+    client.check_input(&messages).context(
+        "The transcript is too long to summarize in one request for parallel translation; \
+        raise the provider's max_input_tokens if the model's context allows, or set \
+        concurrency to 1 to use the rolling summary instead",
+    )?;
+    let summary = client
+        .chat_completion(model_name, messages, false, None)
+        .await?
+        .trim()
+        .to_string();
+
+    Ok(summary)
 }
 
 pub async fn update_summary(
@@ -131,3 +437,60 @@ pub async fn update_summary(
 
     Ok(new_summary)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(n: usize) -> Vec<BatchItem> {
+        (0..n)
+            .map(|id| BatchItem {
+                id,
+                text: format!("line {}", id),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_json_response() {
+        let response = "```json\n{\"translations\": [\
+            {\"id\": 1, \"translated_text\": \"b\"}, {\"id\": 0, \"translated_text\": \"a\"}]}\n```";
+        let responses = parse_json_response(response).unwrap();
+        let (translated, problems) = check_translations(&items(2), responses);
+        assert!(problems.is_empty());
+        assert_eq!(translated[&0], "a");
+
+        assert!(parse_json_response("[0] a").is_err());
+    }
+
+    #[test]
+    fn test_check_rejects_bad_ids() {
+        let (translated, problems) = check_translations(
+            &items(4),
+            parse_line_response("[0] a\n[2] c\n[2] c2\n[3] Line 3\n[9] x"),
+        );
+        let mut accepted: Vec<_> = translated.keys().copied().collect();
+        accepted.sort();
+        assert_eq!(accepted, vec![0]);
+        assert_eq!(
+            problems,
+            vec![
+                "missing ids [1]",
+                "unknown ids [9]",
+                "duplicate ids [2]",
+                "untranslated ids [3]"
+            ]
+        );
+
+        // Nothing to translate in a line without letters
+        assert!(!is_untranslated("♪ 1, 2, 3 ♪", "♪ 1, 2, 3 ♪"));
+    }
+
+    #[test]
+    fn test_repair_context() {
+        let window = items(8);
+        let translated = HashMap::from([(2, "deux".to_string())]);
+        let context = repair_context(&window, &window[4..5], &translated);
+        assert_eq!(context, "line 2 => deux\nline 3\nline 5\nline 6");
+    }
+}
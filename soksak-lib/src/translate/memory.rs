@@ -0,0 +1,196 @@
+use crate::config::Language;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Translations that only apply to lines translated the same way: into the
+/// same language, by the same engine, with the same prompt.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MemoryScope {
+    pub target_lang: Language,
+    pub engine: String,
+    pub prompt_hash: u32,
+}
+
+/// One line of the memory file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub source: String, // normalized
+    pub target_lang: Language,
+    pub engine: String,
+    pub prompt_hash: u32,
+    pub translated: String,
+}
+
+/// Line translations from earlier runs, kept in an append-only JSON Lines
+/// file so identical lines aren't paid for twice.
+pub struct TranslationMemory {
+    path: PathBuf,
+    entries: HashMap<(MemoryScope, String), String>,
+}
+
+impl TranslationMemory {
+    /// `~/.soksak/translation_memory.jsonl`
+    pub fn default_path() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("Could not find home directory")?;
+        Ok(home.join(".soksak/translation_memory.jsonl"))
+    }
+
+    /// Reads the memory at `path`; a missing file is an empty memory. Later
+    /// entries override earlier ones for the same line.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut entries = HashMap::new();
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            // An interrupted write can leave a partial last line
+            let Ok(entry) = serde_json::from_str::<MemoryEntry>(line) else {
+                log::warn!("Skipping unreadable translation memory entry: {}", line);
+                continue;
+            };
+            let scope = MemoryScope {
+                target_lang: entry.target_lang,
+                engine: entry.engine,
+                prompt_hash: entry.prompt_hash,
+            };
+            entries.insert((scope, entry.source), entry.translated);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, scope: &MemoryScope, source: &str) -> Option<&str> {
+        self.entries
+            .get(&(scope.clone(), normalize(source)))
+            .map(String::as_str)
+    }
+
+    /// Remembers `(source, translated)` pairs and appends them to the file.
+    pub fn insert(&mut self, scope: &MemoryScope, pairs: &[(&str, &str)]) -> Result<()> {
+        if pairs.is_empty() {
+            return Ok(());
+        }
+        let mut lines = String::new();
+        for (source, translated) in pairs {
+            let entry = MemoryEntry {
+                source: normalize(source),
+                target_lang: scope.target_lang,
+                engine: scope.engine.clone(),
+                prompt_hash: scope.prompt_hash,
+                translated: translated.to_string(),
+            };
+            lines.push_str(&serde_json::to_string(&entry)?);
+            lines.push('\n');
+            self.entries
+                .insert((scope.clone(), entry.source), entry.translated);
+        }
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {:?}", self.path))?;
+        file.write_all(lines.as_bytes())
+            .with_context(|| format!("Failed to write {:?}", self.path))?;
+        Ok(())
+    }
+
+    /// Writes every entry, without duplicates, as a JSON array.
+    pub fn export(&self, path: &Path) -> Result<()> {
+        let mut entries: Vec<MemoryEntry> = self
+            .entries
+            .iter()
+            .map(|((scope, source), translated)| MemoryEntry {
+                source: source.clone(),
+                target_lang: scope.target_lang,
+                engine: scope.engine.clone(),
+                prompt_hash: scope.prompt_hash,
+                translated: translated.clone(),
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            (&a.engine, a.target_lang.to_string(), &a.source).cmp(&(
+                &b.engine,
+                b.target_lang.to_string(),
+                &b.source,
+            ))
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&entries)?)
+            .with_context(|| format!("Failed to write {:?}", path))
+    }
+}
+
+/// CRC32 of everything that shapes a translation prompt besides the lines.
+pub fn prompt_hash(parts: &[&str]) -> u32 {
+    let mut crc = flate2::Crc::new();
+    for part in parts {
+        crc.update(part.as_bytes());
+        crc.update(&[0]);
+    }
+    crc.sum()
+}
+
+/// Trims and collapses whitespace, so lines differing only in spacing share
+/// an entry.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("translation_memory.jsonl");
+        let scope = MemoryScope {
+            target_lang: Language::Korean,
+            engine: "openai/gpt-4o".to_string(),
+            prompt_hash: prompt_hash(&["Translate naturally."]),
+        };
+
+        let mut memory = TranslationMemory::open(&path).unwrap();
+        assert!(memory.is_empty());
+        memory
+            .insert(&scope, &[("Hello  there. ", "안녕하세요.")])
+            .unwrap();
+
+        let memory = TranslationMemory::open(&path).unwrap();
+        assert_eq!(memory.get(&scope, " Hello there."), Some("안녕하세요."));
+        let other_prompt = MemoryScope {
+            prompt_hash: prompt_hash(&["Translate formally."]),
+            ..scope.clone()
+        };
+        assert_eq!(memory.get(&other_prompt, "Hello there."), None);
+
+        let export_path = dir.path().join("export.json");
+        memory.export(&export_path).unwrap();
+        let exported: Vec<MemoryEntry> =
+            serde_json::from_str(&std::fs::read_to_string(&export_path).unwrap()).unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].source, "Hello there.");
+
+        crate::checkpoint::remove(&path).unwrap();
+        assert!(TranslationMemory::open(&path).unwrap().is_empty());
+    }
+}
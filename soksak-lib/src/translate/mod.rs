@@ -1,14 +1,29 @@
 #[cfg(feature = "apple")]
 pub mod apple;
+pub mod brief;
+pub mod checkpoint;
+pub mod glossary;
 pub mod llm;
+pub mod memory;
+pub mod resegment;
 
+use crate::checkpoint::CheckpointOptions;
 use crate::config::{AppConfig, Edit, FilterConfig, Language, Translate, TranslateEngine};
 use crate::llm::{LlmClient, Message};
+use crate::output;
 use crate::transcribe::TranscriptSegment;
 use anyhow::Result;
+use brief::TranslationBrief;
+use checkpoint::TranslationCheckpoint;
+use glossary::Glossary;
 use indicatif::ProgressBar;
+use memory::{MemoryScope, TranslationMemory};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TranslatedSegment {
     pub start: i64,
     pub end: i64,
@@ -33,38 +48,107 @@ pub struct FilterResponse {
     pub remove_ids: Vec<usize>,
 }
 
+/// What every window needs to be translated, shared by windows running in
+/// parallel.
+struct WindowJob {
+    #[cfg_attr(not(feature = "apple"), allow(dead_code))]
+    source_lang: Language, // only the Apple engine takes a source language
+    translate_config: Translate,
+    edit_config: Option<Edit>,
+    app_config: AppConfig,
+    trans: Option<(LlmClient, String)>, // client and model name, for the LLM engine
+    brief: String,                      // rendered for the target language, empty without a brief
+    glossary: Option<Glossary>,
+    memory: Option<Mutex<TranslationMemory>>,
+    memory_scope: MemoryScope,
+}
+
+/// A translated window, and its segments that stayed untranslated.
+struct WindowOutput {
+    segments: Vec<TranslatedSegment>,
+    untranslated: Vec<TranslatedSegment>,
+}
+
+/// State a translation shares with other runs besides its outputs.
+#[derive(Debug, Clone, Default)]
+pub struct TranslationOptions {
+    pub checkpoint: Option<CheckpointOptions>,
+    pub brief: Option<TranslationBrief>, // from `brief::prepare`
+    pub memory_path: Option<PathBuf>,    // translation memory, bypassed if None
+    pub summary: Option<String>,         // from `prepare_summary`, for parallel windows
+}
+
+/// The client and model name for an LLM engine's `provider/model`.
+fn llm_client(model: &str, app_config: &AppConfig) -> Result<(LlmClient, String)> {
+    let (provider_id, model_name) = model.split_once('/').unwrap_or((model, "default"));
+    let provider_config = app_config
+        .llm
+        .providers
+        .iter()
+        .find(|p| p.id == provider_id)
+        .ok_or_else(|| anyhow::anyhow!("Provider {} not found", provider_id))?;
+    Ok((
+        LlmClient::new(provider_config.clone())?,
+        model_name.to_string(),
+    ))
+}
+
+/// Summarizes the whole transcript once for all target languages, or returns
+/// `None` unless windows are translated in parallel. Parallel windows can't
+/// wait for the rolling summary of the ones before them, so they share this
+/// one instead.
+pub async fn prepare_summary(
+    translate_config: &Translate,
+    app_config: &AppConfig,
+    segments: &[TranscriptSegment],
+) -> Result<Option<String>> {
+    let (model, concurrency) = match &translate_config.engine {
+        TranslateEngine::LLM {
+            model, concurrency, ..
+        } => (model, concurrency.unwrap_or(1)),
+        #[cfg(feature = "apple")]
+        TranslateEngine::Apple { .. } => return Ok(None),
+    };
+    if concurrency <= 1 {
+        return Ok(None);
+    }
+    let (client, model_name) = llm_client(model, app_config)?;
+    let summary = llm::summarize_transcript(&client, &model_name, segments).await?;
+    Ok(Some(summary))
+}
+
 pub async fn process_translation(
-    _source_lang: &Language,
+    source_lang: &Language,
     translate_config: &Translate,
     edit_config: Option<&Edit>,
     segments: Vec<TranscriptSegment>,
     app_config: &AppConfig,
+    options: &TranslationOptions,
     pb: &ProgressBar,
 ) -> Result<Vec<TranslatedSegment>> {
     let mut translated_segments = Vec::new();
+    let mut untranslated = Vec::new();
     let mut summary = String::from("No context yet.");
 
-    // For LLM engine, we need to initialize the client for summary updates
-    // We also need a client for the Edit phase if it exists
-    // And for filtering if it exists
+    // Translate whole sentences rather than the fragments they were heard in
+    let sentences = translate_config
+        .resegment
+        .as_ref()
+        .map(|conf| resegment::Sentences::merge(&segments, conf));
+    let segments = match &sentences {
+        Some(sentences) => {
+            let merged = sentences.segments();
+            pb.set_length(merged.len() as u64);
+            merged
+        }
+        None => segments,
+    };
 
     // Determine the LLM client for translation if needed
-    let (trans_client, trans_model) = match &translate_config.engine {
-        TranslateEngine::LLM { model, .. } => {
-            let (provider_id, model_name) = model.split_once('/').unwrap_or((model, "default"));
-            let provider_config = app_config
-                .llm
-                .providers
-                .iter()
-                .find(|p| p.id == provider_id)
-                .ok_or_else(|| anyhow::anyhow!("Provider {} not found", provider_id))?;
-            (
-                Some(LlmClient::new(provider_config.clone())),
-                Some(model_name),
-            )
-        }
+    let trans = match &translate_config.engine {
+        TranslateEngine::LLM { model, .. } => Some(llm_client(model, app_config)?),
         #[cfg(feature = "apple")]
-        TranslateEngine::Apple { .. } => (None, None),
+        TranslateEngine::Apple { .. } => None,
     };
 
     // Determine window size
@@ -74,111 +158,408 @@ pub async fn process_translation(
         TranslateEngine::Apple { window } => window.unwrap_or(100),
     };
 
-    // Process in chunks
-    for chunk in segments.chunks(window_size) {
-        let batch_items: Vec<BatchItem> = chunk
-            .iter()
-            .enumerate()
-            .map(|(i, seg)| BatchItem {
-                id: i, // Relative ID within the batch
-                text: seg.text.replace('\r', "").replace('\n', ""),
+    let concurrency = match &translate_config.engine {
+        TranslateEngine::LLM { concurrency, .. } => concurrency.unwrap_or(1).max(1),
+        #[cfg(feature = "apple")]
+        TranslateEngine::Apple { .. } => 1,
+    };
+    let parallel = concurrency > 1;
+
+    let engine = match &translate_config.engine {
+        TranslateEngine::LLM { model, .. } => model.clone(),
+        #[cfg(feature = "apple")]
+        TranslateEngine::Apple { .. } => "apple".to_string(),
+    };
+    let source_crc32 = checkpoint::source_crc32(&segments);
+    let checkpoint = options.checkpoint.as_ref();
+
+    // Windows are fixed by the window size, so a checkpoint only needs to
+    // know how many were finished
+    let mut completed_windows = 0;
+    if let Some(options) = checkpoint.filter(|o| o.resume) {
+        match crate::checkpoint::load::<TranslationCheckpoint>(&options.path)? {
+            Some(saved) => {
+                saved.validate(
+                    source_crc32,
+                    &engine,
+                    &translate_config.target_lang,
+                    window_size,
+                )?;
+                pb.println(format!(
+                    "Resuming translation after window {} of {}",
+                    saved.completed_windows,
+                    segments.len().div_ceil(window_size)
+                ));
+                completed_windows = saved.completed_windows;
+                summary = saved.summary;
+                translated_segments = saved.segments;
+                untranslated = saved.untranslated;
+                pb.inc((completed_windows * window_size).min(segments.len()) as u64);
+            }
+            None => pb.println("No translation checkpoint found, starting from the beginning"),
+        }
+    }
+
+    let glossary = translate_config
+        .glossary
+        .as_ref()
+        .map(Glossary::load)
+        .transpose()?;
+
+    let brief = options
+        .brief
+        .as_ref()
+        .map(|b| b.to_prompt(translate_config.target_lang))
+        .unwrap_or_default();
+
+    // Windows translated in parallel all share the summary of the whole transcript
+    if parallel && completed_windows == 0 {
+        if let Some(whole) = &options.summary {
+            summary = whole.clone();
+        }
+    }
+
+    let memory = match &options.memory_path {
+        Some(path) => {
+            let memory = TranslationMemory::open(path)?;
+            log::debug!("Translation memory {:?}: {} entries", path, memory.len());
+            Some(Mutex::new(memory))
+        }
+        None => None,
+    };
+    // Cached lines are only reused under the prompt they were translated with
+    let system_prompt = match &translate_config.engine {
+        TranslateEngine::LLM { system_prompt, .. } => system_prompt.clone().unwrap_or_default(),
+        #[cfg(feature = "apple")]
+        TranslateEngine::Apple { .. } => String::new(),
+    };
+    let memory_scope = MemoryScope {
+        target_lang: translate_config.target_lang,
+        engine: engine.clone(),
+        prompt_hash: memory::prompt_hash(&[
+            &system_prompt,
+            &brief,
+            &glossary
+                .as_ref()
+                .map(|g| glossary::to_prompt(&g.entries().iter().collect::<Vec<_>>()))
+                .unwrap_or_default(),
+        ]),
+    };
+
+    let job = Arc::new(WindowJob {
+        source_lang: *source_lang,
+        translate_config: translate_config.clone(),
+        edit_config: edit_config.cloned(),
+        app_config: app_config.clone(),
+        trans,
+        brief,
+        glossary,
+        memory,
+        memory_scope,
+    });
+    let mut windows = segments
+        .chunks(window_size)
+        .map(<[TranscriptSegment]>::to_vec)
+        .enumerate()
+        .skip(completed_windows);
+
+    // In parallel mode every window is spawned up front and waits for a
+    // permit; results are still collected in order
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+    let mut handles: VecDeque<_> = if parallel {
+        windows
+            .by_ref()
+            .map(|(window_index, chunk)| {
+                let job = Arc::clone(&job);
+                let semaphore = Arc::clone(&semaphore);
+                let summary = summary.clone();
+                tokio::spawn(async move {
+                    let _permit = semaphore.acquire_owned().await?;
+                    let output = process_window(&job, &chunk, &summary).await?;
+                    anyhow::Ok((window_index, chunk.len(), output))
+                })
             })
-            .collect();
+            .collect()
+    } else {
+        VecDeque::new()
+    };
 
-        // 1. Translate Batch
-        let current_batch_results = match &translate_config.engine {
-            TranslateEngine::LLM {
-                model: _,
-                system_prompt,
-                window: _,
-            } => {
-                if let (Some(client), Some(model)) = (&trans_client, trans_model) {
-                    llm::translate_batch(
-                        client,
-                        model,
-                        &batch_items,
-                        &translate_config.target_lang.to_string(),
-                        system_prompt.as_deref().unwrap_or(""),
-                        &summary,
-                    )
-                    .await?
-                } else {
-                    unreachable!("LLM client should be initialized for LLM engine");
-                }
+    loop {
+        let result = if parallel {
+            match handles.pop_front() {
+                Some(handle) => handle.await.map_err(anyhow::Error::from).and_then(|r| r),
+                None => break,
             }
-            #[cfg(feature = "apple")]
-            TranslateEngine::Apple { .. } => {
-                let source_lang = match _source_lang {
-                    Language::Auto => None,
-                    _ => Some(_source_lang.to_string()),
-                };
-
-                apple::translate_batch(
-                    &batch_items,
-                    source_lang.as_deref(),
-                    &translate_config.target_lang.to_string(),
-                )
-                .await?
+        } else {
+            match windows.next() {
+                Some((window_index, chunk)) => process_window(&job, &chunk, &summary)
+                    .await
+                    .map(|output| (window_index, chunk.len(), output)),
+                None => break,
+            }
+        };
+        let (window_index, window_len, output) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                handles.iter().for_each(|handle| handle.abort());
+                return Err(e);
             }
         };
 
-        // Map translations back to segments to preserve start/end times
-        let mut mapped_results: Vec<TranslatedSegment> = Vec::new();
-        for (i, segment) in chunk.iter().enumerate() {
-            let translated_text = current_batch_results
-                .iter()
-                .find(|t| t.id == i)
-                .map(|t| t.translated_text.clone())
-                .unwrap_or_else(|| segment.text.clone());
-
-            mapped_results.push(TranslatedSegment {
-                start: segment.start,
-                end: segment.end,
-                original: segment.text.clone(),
-                translated: translated_text,
-            });
+        // Update Summary (Only for LLM engine usually)
+        if !parallel {
+            if let Some((client, model)) = &job.trans {
+                if !output.segments.is_empty() {
+                    summary =
+                        llm::update_summary(client, model, &summary, &output.segments).await?;
+                }
+            }
         }
 
-        // 2. Edit Batch (Refinement)
-        if let Some(edit) = edit_config {
-            if let Some(instructions) = &edit.instructions {
-                if !instructions.is_empty() {
-                    mapped_results = edit_batch(
-                        mapped_results,
-                        edit,
-                        app_config,
-                        &translate_config.target_lang,
-                    )
-                    .await?;
-                }
+        translated_segments.extend(output.segments);
+        untranslated.extend(output.untranslated);
+        pb.inc(window_len as u64);
+
+        if let Some(options) = checkpoint {
+            crate::checkpoint::save(
+                &TranslationCheckpoint {
+                    source_crc32,
+                    engine: engine.clone(),
+                    target_lang: translate_config.target_lang,
+                    window_size,
+                    completed_windows: window_index + 1,
+                    summary: summary.clone(),
+                    segments: translated_segments.clone(),
+                    untranslated: untranslated.clone(),
+                },
+                &options.path,
+            )?;
+        }
+    }
+
+    if !untranslated.is_empty() {
+        pb.println(format!(
+            "Warning: {} segments stayed untranslated and keep their original text:",
+            untranslated.len()
+        ));
+        for segment in &untranslated {
+            pb.println(format!(
+                "  {} --> {}  {}",
+                output::format_timestamp(segment.start),
+                output::format_timestamp(segment.end),
+                segment.original
+            ));
+        }
+    }
+
+    if let Some(glossary) = &job.glossary {
+        let violations = glossary.check(&translated_segments);
+        if !violations.is_empty() {
+            pb.println(format!(
+                "Warning: {} segments miss a glossary term in their translation:",
+                violations.len()
+            ));
+            for violation in &violations {
+                pb.println(format!(
+                    "  {} --> {}  {:?} should be translated as {:?}",
+                    output::format_timestamp(violation.start),
+                    output::format_timestamp(violation.end),
+                    violation.term,
+                    violation.expected
+                ));
             }
         }
+    }
+
+    match &sentences {
+        Some(sentences) => Ok(sentences.redistribute(translated_segments)),
+        None => Ok(translated_segments),
+    }
+}
+
+/// Translates, edits and filters one window.
+async fn process_window(
+    job: &WindowJob,
+    chunk: &[TranscriptSegment],
+    summary: &str,
+) -> Result<WindowOutput> {
+    let translate_config = &job.translate_config;
+    let batch_items: Vec<BatchItem> = chunk
+        .iter()
+        .enumerate()
+        .map(|(i, seg)| BatchItem {
+            id: i, // Relative ID within the batch
+            text: seg.text.replace(['\r', '\n'], ""),
+        })
+        .collect();
 
-        // 3. Filter Batch
-        // Check if filtering is configured in Edit config
-        if let Some(edit) = edit_config {
-            if let Some(filters) = &edit.filters {
-                if !filters.is_empty() {
-                    // We need a default LLM for filtering. Use edit.default_model
-                    mapped_results =
-                        filter_batch(mapped_results, filters, app_config, &edit.default_model)
-                            .await?;
+    // 1. Translate Batch
+    // Lines translated before under the same scope come from the memory
+    let mut cached = Vec::new();
+    let mut pending = Vec::new();
+    match &job.memory {
+        Some(memory) => {
+            let memory = memory.lock().unwrap();
+            for item in batch_items {
+                match memory.get(&job.memory_scope, &item.text) {
+                    Some(translated) => cached.push(BatchTranslationResponse {
+                        id: item.id,
+                        translated_text: translated.to_string(),
+                    }),
+                    None => pending.push(item),
                 }
             }
         }
+        None => pending = batch_items,
+    }
+
+    let (mut current_batch_results, untranslated_ids) = if pending.is_empty() {
+        (Vec::new(), Vec::new())
+    } else {
+        translate_items(job, &pending, summary).await?
+    };
 
-        // 4. Update Summary (Only for LLM engine usually)
-        if let (Some(client), Some(model)) = (&trans_client, trans_model) {
-            if !mapped_results.is_empty() {
-                summary = llm::update_summary(client, model, &summary, &mapped_results).await?;
+    if let Some(memory) = &job.memory {
+        let pairs: Vec<(&str, &str)> = current_batch_results
+            .iter()
+            .filter(|t| !untranslated_ids.contains(&t.id))
+            .filter_map(|t| {
+                let item = pending.iter().find(|i| i.id == t.id)?;
+                Some((item.text.as_str(), t.translated_text.as_str()))
+            })
+            .collect();
+        memory.lock().unwrap().insert(&job.memory_scope, &pairs)?;
+    }
+    current_batch_results.extend(cached);
+
+    let untranslated = untranslated_ids
+        .iter()
+        .map(|&i| TranslatedSegment {
+            start: chunk[i].start,
+            end: chunk[i].end,
+            original: chunk[i].text.clone(),
+            translated: chunk[i].text.clone(),
+        })
+        .collect();
+
+    // Map translations back to segments to preserve start/end times
+    let mut mapped_results: Vec<TranslatedSegment> = Vec::new();
+    for (i, segment) in chunk.iter().enumerate() {
+        let translated_text = current_batch_results
+            .iter()
+            .find(|t| t.id == i)
+            .map(|t| t.translated_text.clone())
+            .unwrap_or_else(|| segment.text.clone());
+
+        mapped_results.push(TranslatedSegment {
+            start: segment.start,
+            end: segment.end,
+            original: segment.text.clone(),
+            translated: translated_text,
+        });
+    }
+
+    // 2. Edit Batch (Refinement)
+    if let Some(edit) = &job.edit_config {
+        if let Some(instructions) = &edit.instructions {
+            if !instructions.is_empty() {
+                mapped_results = edit_batch(
+                    mapped_results,
+                    edit,
+                    &job.app_config,
+                    &translate_config.target_lang,
+                    &job.brief,
+                )
+                .await?;
             }
         }
+    }
 
-        translated_segments.extend(mapped_results);
-        pb.inc(chunk.len() as u64);
+    // 3. Filter Batch
+    // Check if filtering is configured in Edit config
+    if let Some(edit) = &job.edit_config {
+        if let Some(filters) = &edit.filters {
+            if !filters.is_empty() {
+                // We need a default LLM for filtering. Use edit.default_model
+                mapped_results = filter_batch(
+                    mapped_results,
+                    filters,
+                    &job.app_config,
+                    &edit.default_model,
+                )
+                .await?;
+            }
+        }
     }
 
-    Ok(translated_segments)
+    Ok(WindowOutput {
+        segments: mapped_results,
+        untranslated,
+    })
+}
+
+/// Translates `batch_items` with the configured engine. Returns the
+/// translations and the ids that stayed untranslated.
+async fn translate_items(
+    job: &WindowJob,
+    batch_items: &[BatchItem],
+    summary: &str,
+) -> Result<(Vec<BatchTranslationResponse>, Vec<usize>)> {
+    let translate_config = &job.translate_config;
+    let results = match &translate_config.engine {
+        TranslateEngine::LLM {
+            system_prompt,
+            repair_attempts,
+            ..
+        } => {
+            if let Some((client, model)) = &job.trans {
+                // Only the terms that occur in this window
+                let glossary = job
+                    .glossary
+                    .as_ref()
+                    .map(|g| {
+                        let texts: Vec<&str> =
+                            batch_items.iter().map(|i| i.text.as_str()).collect();
+                        glossary::to_prompt(&g.entries_in(&texts))
+                    })
+                    .unwrap_or_default();
+                let window = llm::translate_window(
+                    client,
+                    model,
+                    batch_items,
+                    &translate_config.target_lang.to_string(),
+                    &format!(
+                        "{}{}{}",
+                        job.brief,
+                        glossary,
+                        system_prompt.as_deref().unwrap_or("")
+                    ),
+                    summary,
+                    repair_attempts.unwrap_or(3).max(1),
+                )
+                .await?;
+                (window.translations, window.untranslated)
+            } else {
+                unreachable!("LLM client should be initialized for LLM engine");
+            }
+        }
+        #[cfg(feature = "apple")]
+        TranslateEngine::Apple { .. } => {
+            let source_lang = match job.source_lang {
+                Language::Auto => None,
+                _ => Some(job.source_lang.to_string()),
+            };
+
+            let translations = apple::translate_batch(
+                batch_items,
+                source_lang.as_deref(),
+                &translate_config.target_lang.to_string(),
+            )
+            .await?;
+            (translations, Vec::new())
+        }
+    };
+    Ok(results)
 }
 
 async fn edit_batch(
@@ -186,6 +567,7 @@ async fn edit_batch(
     edit_config: &Edit,
     app_config: &AppConfig,
     target_lang: &Language,
+    brief: &str,
 ) -> Result<Vec<TranslatedSegment>> {
     let (provider_id, model_name) = edit_config
         .default_model
@@ -198,7 +580,7 @@ async fn edit_batch(
         .iter()
         .find(|p| p.id == provider_id)
         .ok_or_else(|| anyhow::anyhow!("Provider {} not found", provider_id))?;
-    let client = LlmClient::new(provider_config.clone());
+    let client = LlmClient::new(provider_config.clone())?;
 
     // Join all texts with ID prefixes
     let batch_text = batch
@@ -215,7 +597,7 @@ async fn edit_batch(
         .unwrap_or_default();
 
     let system_prompt = format!(
-        "You are a professional editor. Refine the following translated sentences based on these instructions:\n\
+        "{}You are a professional editor. Refine the following translated sentences based on these instructions:\n\
         Target Language: {}\n\
         Instructions:\n\
         {}\n\
@@ -232,7 +614,7 @@ async fn edit_batch(
         Maintain the exact ID for each line.\n\
         Do not merge, split, or reorder lines.\n\
         Output ONLY the refined text with IDs, no other comments or explanations.",
-        target_lang, instructions_str
+        brief, target_lang, instructions_str
     );
 
     let messages = vec![
@@ -298,7 +680,7 @@ async fn edit_batch(
 
 async fn filter_batch(
     batch: Vec<TranslatedSegment>,
-    filters: &Vec<FilterConfig>,
+    filters: &[FilterConfig],
     app_config: &AppConfig,
     default_llm_str: &str,
 ) -> Result<Vec<TranslatedSegment>> {
@@ -340,7 +722,7 @@ async fn filter_batch(
         .unwrap_or((filter_llm_str, "default"));
 
     let f_client = if let Some(p) = app_config.llm.providers.iter().find(|p| p.id == f_prov_id) {
-        LlmClient::new(p.clone())
+        LlmClient::new(p.clone())?
     } else {
         // Fallback to default provider if specific filter provider not found
         // We need to find the default provider config again
@@ -353,7 +735,7 @@ async fn filter_batch(
             .iter()
             .find(|p| p.id == def_prov_id)
             .ok_or_else(|| anyhow::anyhow!("Default provider {} not found", def_prov_id))?;
-        LlmClient::new(def_conf.clone())
+        LlmClient::new(def_conf.clone())?
     };
 
     let schema = serde_json::json!({
@@ -0,0 +1,277 @@
+use crate::config::ResegmentConfig;
+use crate::transcribe::TranscriptSegment;
+use crate::translate::TranslatedSegment;
+use std::collections::HashMap;
+
+/// Characters that end a sentence, possibly followed by closing quotes.
+const SENTENCE_ENDS: &[char] = &['.', '?', '!', '…', '。', '？', '！'];
+const CLOSING_QUOTES: &[char] = &['"', '\'', '”', '’', ')', '」', '』', '》'];
+
+/// Transcript segments merged into whole sentences, remembering which
+/// segments each sentence was made of.
+pub struct Sentences {
+    groups: Vec<(TranscriptSegment, Vec<TranscriptSegment>)>,
+}
+
+impl Sentences {
+    /// Merges consecutive segments until one ends a sentence, a pause longer
+    /// than `max_gap` follows, or the sentence would exceed `max_chars`.
+    pub fn merge(segments: &[TranscriptSegment], conf: &ResegmentConfig) -> Self {
+        let max_chars = conf.max_chars.unwrap_or(300);
+        let max_gap = (conf.max_gap.unwrap_or(1.5) * 100.0).round() as i64;
+
+        let mut groups: Vec<(TranscriptSegment, Vec<TranscriptSegment>)> = Vec::new();
+        for segment in segments {
+            if let Some((sentence, parts)) = groups.last_mut() {
+                let last = parts.last().unwrap();
+                let joinable = !ends_sentence(&last.text)
+                    && segment.start - last.end <= max_gap
+                    && sentence.text.chars().count() + segment.text.chars().count() <= max_chars
+                    && sentence.flagged.is_none()
+                    && segment.flagged.is_none();
+                if joinable {
+                    sentence.end = segment.end;
+                    sentence.text = join_text(&sentence.text, &segment.text);
+                    sentence.words.extend(segment.words.iter().cloned());
+                    parts.push(segment.clone());
+                    continue;
+                }
+            }
+            let sentence = TranscriptSegment {
+                quality: None,
+                ..segment.clone()
+            };
+            groups.push((sentence, vec![segment.clone()]));
+        }
+        Self { groups }
+    }
+
+    /// The merged sentences, to translate in place of the segments.
+    pub fn segments(&self) -> Vec<TranscriptSegment> {
+        self.groups
+            .iter()
+            .map(|(sentence, _)| sentence.clone())
+            .collect()
+    }
+
+    /// Splits each translated sentence back over the time spans of the
+    /// segments it was merged from. Sentences are matched by their timing,
+    /// so ones dropped by a filter stay dropped.
+    pub fn redistribute(&self, translated: Vec<TranslatedSegment>) -> Vec<TranslatedSegment> {
+        let parts_by_span: HashMap<(i64, i64), &[TranscriptSegment]> = self
+            .groups
+            .iter()
+            .map(|(sentence, parts)| ((sentence.start, sentence.end), parts.as_slice()))
+            .collect();
+
+        let mut result = Vec::new();
+        for sentence in translated {
+            match parts_by_span.get(&(sentence.start, sentence.end)) {
+                Some(parts) if parts.len() > 1 => {
+                    result.extend(split_translation(&sentence.translated, parts))
+                }
+                _ => result.push(sentence),
+            }
+        }
+        result
+    }
+}
+
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end()
+        .trim_end_matches(CLOSING_QUOTES)
+        .ends_with(SENTENCE_ENDS)
+}
+
+/// Scripts written without spaces between words.
+fn is_unspaced(c: char) -> bool {
+    matches!(c,
+        '\u{3000}'..='\u{30FF}' // CJK punctuation, hiragana, katakana
+        | '\u{3400}'..='\u{9FFF}' // CJK ideographs
+        | '\u{FF00}'..='\u{FFEF}' // full-width forms
+        | '\u{0E00}'..='\u{0E7F}' // Thai
+    )
+}
+
+fn is_break(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_ascii_punctuation()
+        || SENTENCE_ENDS.contains(&c)
+        || "、，；：".contains(c)
+}
+
+/// Joins segment texts, adding a space unless either side already has one
+/// or both sides are in a script written without spaces.
+fn join_text(a: &str, b: &str) -> String {
+    let (Some(last), Some(first)) = (a.chars().last(), b.chars().next()) else {
+        return format!("{}{}", a, b);
+    };
+    if last.is_whitespace() || first.is_whitespace() || (is_unspaced(last) && is_unspaced(first)) {
+        format!("{}{}", a, b)
+    } else {
+        format!("{} {}", a, b)
+    }
+}
+
+/// How much of the sentence each part holds: its speech time if every part
+/// has word timestamps, its share of the characters otherwise.
+fn part_weights(parts: &[TranscriptSegment]) -> Vec<f64> {
+    if parts.iter().all(|p| !p.words.is_empty()) {
+        let weights: Vec<f64> = parts
+            .iter()
+            .map(|p| {
+                p.words
+                    .iter()
+                    .map(|w| (w.end - w.start).max(1))
+                    .sum::<i64>() as f64
+            })
+            .collect();
+        if weights.iter().sum::<f64>() > 0.0 {
+            return weights;
+        }
+    }
+    parts
+        .iter()
+        .map(|p| p.text.trim().chars().count().max(1) as f64)
+        .collect()
+}
+
+/// Cuts a translated sentence into one piece per part, in proportion to the
+/// parts' weights, moving each cut to the nearest space or punctuation mark.
+fn split_translation(translated: &str, parts: &[TranscriptSegment]) -> Vec<TranslatedSegment> {
+    let chars: Vec<char> = translated.trim().chars().collect();
+    let weights = part_weights(parts);
+    let total: f64 = weights.iter().sum();
+
+    // Positions a cut may snap to: after a space or punctuation mark
+    let boundaries: Vec<usize> = (1..chars.len())
+        .filter(|&i| is_break(chars[i - 1]))
+        .collect();
+    let has_spaces = chars.iter().any(|c| c.is_whitespace());
+
+    let mut cuts = vec![0];
+    let mut cumulative = 0.0;
+    for weight in &weights[..weights.len() - 1] {
+        cumulative += weight;
+        let target = (cumulative / total * chars.len() as f64).round() as usize;
+        let nearest = boundaries
+            .iter()
+            .copied()
+            .min_by_key(|&b| b.abs_diff(target))
+            .filter(|&b| has_spaces || b.abs_diff(target) <= 3);
+        let previous = *cuts.last().unwrap();
+        cuts.push(nearest.unwrap_or(target).clamp(previous, chars.len()));
+    }
+    cuts.push(chars.len());
+
+    let mut pieces: Vec<TranslatedSegment> = Vec::new();
+    for (part, range) in parts.iter().zip(cuts.windows(2)) {
+        let text: String = chars[range[0]..range[1]].iter().collect();
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            // Nothing left for this span; let the previous piece run over it
+            if let Some(previous) = pieces.last_mut() {
+                previous.end = part.end;
+                previous.original = join_text(&previous.original, &part.text);
+                continue;
+            }
+        }
+        match pieces.last_mut() {
+            // The first span came out empty; this piece starts there instead
+            Some(previous) if previous.translated.is_empty() => {
+                previous.end = part.end;
+                previous.original = join_text(&previous.original, &part.text);
+                previous.translated = text;
+            }
+            _ => pieces.push(TranslatedSegment {
+                start: part.start,
+                end: part.end,
+                original: part.text.clone(),
+                translated: text,
+            }),
+        }
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcribe::TranscriptWord;
+
+    fn segment(start: i64, end: i64, text: &str) -> TranscriptSegment {
+        TranscriptSegment {
+            start,
+            end,
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge_sentences() {
+        let segments = vec![
+            segment(0, 200, " I went to the store"),
+            segment(200, 400, " because we ran out of milk."),
+            segment(400, 500, " Then I came home"),
+            // A long pause ends the sentence even without punctuation
+            segment(800, 900, " and slept."),
+        ];
+        let sentences = Sentences::merge(&segments, &ResegmentConfig::default());
+        let merged = sentences.segments();
+
+        assert_eq!(merged.len(), 3);
+        assert_eq!(
+            merged[0].text,
+            " I went to the store because we ran out of milk."
+        );
+        assert_eq!((merged[0].start, merged[0].end), (0, 400));
+        assert_eq!(join_text("東京に", "行きました。"), "東京に行きました。");
+    }
+
+    #[test]
+    fn test_redistribute_by_characters() {
+        let segments = vec![
+            segment(0, 200, "I went to the store"),
+            segment(200, 400, "because we ran out of milk."),
+        ];
+        let sentences = Sentences::merge(&segments, &ResegmentConfig::default());
+        let translated = vec![TranslatedSegment {
+            start: 0,
+            end: 400,
+            original: "I went to the store because we ran out of milk.".to_string(),
+            translated: "우유가 떨어져서 가게에 갔다.".to_string(),
+        }];
+
+        let split = sentences.redistribute(translated);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].translated, "우유가 떨어져서");
+        assert_eq!(split[1].translated, "가게에 갔다.");
+        assert_eq!((split[1].start, split[1].end), (200, 400));
+        assert_eq!(split[1].original, "because we ran out of milk.");
+    }
+
+    #[test]
+    fn test_redistribute_by_word_timestamps() {
+        let word = |start, end| TranscriptWord {
+            start,
+            end,
+            text: String::new(),
+            probability: 1.0,
+        };
+        let mut segments = vec![segment(0, 300, "Well,"), segment(300, 400, "no.")];
+        // A drawn-out "Well," takes most of the speech time
+        segments[0].words = vec![word(0, 300)];
+        segments[1].words = vec![word(300, 400)];
+        let sentences = Sentences::merge(&segments, &ResegmentConfig::default());
+
+        let split = sentences.redistribute(vec![TranslatedSegment {
+            start: 0,
+            end: 400,
+            original: "Well, no.".to_string(),
+            translated: "Eh bien, non.".to_string(),
+        }]);
+        assert_eq!(split[0].translated, "Eh bien,");
+        assert_eq!(split[1].translated, "non.");
+    }
+}